@@ -0,0 +1,2 @@
+pub mod markers; // marker lines of the output files
+pub mod naming; // observation file names shared by estractor and websvc
@@ -0,0 +1,36 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+// closing line of an estractor output file
+pub const END_MARKER: &str = "-- END";
+// output cut short by the deadline or SIGTERM
+pub const PARTIAL_MARKER: &str = "-- PARTIAL";
+// whole content of an observation that repeats an earlier one:
+// "-- UNCHANGED <file name of the earlier observation>"
+pub const UNCHANGED_MARKER: &str = "-- UNCHANGED";
+
+pub fn unchanged_line(file_name: &str) -> String {
+    format!("{} {}", UNCHANGED_MARKER, file_name)
+}
+
+// file name referenced by an unchanged marker file, None for a regular observation
+pub fn unchanged_target(path: &Path) -> Option<String> {
+    let mut first_line = String::new();
+    BufReader::new(File::open(path).ok()?).read_line(&mut first_line).ok()?;
+    let target = first_line.trim().strip_prefix(UNCHANGED_MARKER)?.trim();
+    if target.is_empty() { None } else { Some(target.to_string()) }
+}
+
+// follow unchanged markers to the observation that holds the quotes
+pub fn resolve_unchanged(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    // markers always point at a regular file, the limit only guards against hand-edited loops
+    for _ in 0..8 {
+        match unchanged_target(&path) {
+            Some(target) => path = path.with_file_name(target),
+            None => break,
+        }
+    }
+    path
+}
@@ -0,0 +1,228 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
+
+// observation file name: <source>-<obsdatetime>.<ext>
+// obsdatetime is ISO-8601 basic format, so it never contains '-' in UTC
+pub const UTC_FORMAT: &str = "%Y%m%dT%H%M%SZ"; // 20260127T192053Z
+pub const OFFSET_FORMAT: &str = "%Y%m%dT%H%M%S%z"; // 20260127T202053+0100
+// format used before the naming scheme, local time of the container
+pub const LEGACY_FORMAT: &str = "%Y-%m-%d-%H-%M-%S"; // 2026-01-27-19-20-53
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NamingScheme {
+    Utc,
+    Offset(FixedOffset),
+    Legacy(FixedOffset),
+}
+
+impl FromStr for NamingScheme {
+    type Err = String;
+
+    // "utc", "legacy" or an explicit offset such as "+01:00"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "utc" | "z" => Ok(NamingScheme::Utc),
+            "legacy" => Ok(NamingScheme::Legacy(utc_offset())),
+            offset => parse_offset(offset)
+                .map(NamingScheme::Offset)
+                .ok_or(format!("invalid naming scheme: {} (utc, legacy or +HH:MM)", s)),
+        }
+    }
+}
+
+impl NamingScheme {
+    pub fn obsdatetime(&self, at: DateTime<Utc>) -> String {
+        match self {
+            NamingScheme::Utc => at.format(UTC_FORMAT).to_string(),
+            NamingScheme::Offset(offset) => at.with_timezone(offset).format(OFFSET_FORMAT).to_string(),
+            NamingScheme::Legacy(offset) => at.with_timezone(offset).format(LEGACY_FORMAT).to_string(),
+        }
+    }
+
+    pub fn file_name(&self, source: &str, at: DateTime<Utc>, ext: &str) -> String {
+        obs_file_name(source, &self.obsdatetime(at), ext)
+    }
+}
+
+pub fn utc_offset() -> FixedOffset {
+    FixedOffset::east_opt(0).unwrap()
+}
+
+pub fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let offset = offset.trim();
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = offset[1..].replace(":", "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+pub fn obs_file_name(source: &str, obsdatetime: &str, ext: &str) -> String {
+    format!("{}-{}.{}", source, obsdatetime, ext)
+}
+
+// legacy names carry no offset, `legacy_tz` says which timezone they were written in;
+// the hour repeated when DST ends is read as its first occurrence
+pub fn parse_obsdatetime<Tz: TimeZone>(obsdatetime: &str, legacy_tz: &Tz) -> Option<DateTime<FixedOffset>> {
+    if let Ok(at) = NaiveDateTime::parse_from_str(obsdatetime, UTC_FORMAT) {
+        return Some(at.and_utc().fixed_offset());
+    }
+    if let Ok(at) = DateTime::parse_from_str(obsdatetime, OFFSET_FORMAT) {
+        return Some(at);
+    }
+    let at = NaiveDateTime::parse_from_str(obsdatetime, LEGACY_FORMAT).ok()?;
+    legacy_tz.from_local_datetime(&at).earliest().map(|at| at.fixed_offset())
+}
+
+#[derive(Debug, Clone)]
+pub struct ObsFile {
+    pub file_name: String,
+    pub source: String,
+    pub obsdatetime: String,
+    pub at: DateTime<FixedOffset>,
+    pub legacy: bool,
+}
+
+// split <source>-<obsdatetime>.<ext>, the source may itself contain '-'
+pub fn parse_file_name<Tz: TimeZone>(file_name: &str, legacy_tz: &Tz) -> Option<ObsFile> {
+    let (stem, _ext) = file_name.rsplit_once('.')?;
+    for (i, _) in stem.match_indices('-') {
+        let (source, obsdatetime) = (&stem[..i], &stem[i + 1..]);
+        if source.is_empty() {
+            continue;
+        }
+        if let Some(at) = parse_obsdatetime(obsdatetime, legacy_tz) {
+            return Some(ObsFile {
+                file_name: file_name.to_string(),
+                source: source.to_string(),
+                obsdatetime: obsdatetime.to_string(),
+                at,
+                legacy: NaiveDateTime::parse_from_str(obsdatetime, LEGACY_FORMAT).is_ok(),
+            });
+        }
+    }
+    None
+}
+
+// observation files of one source in `dir`, oldest first
+pub fn list_observations(dir: &str, source: &str, ext: &str) -> Vec<ObsFile> {
+    let mut observations: Vec<ObsFile> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| Path::new(name).extension().is_some_and(|e| e == ext))
+            .filter_map(|name| parse_file_name(&name, &utc_offset()))
+            .filter(|obs| obs.source == source)
+            .collect(),
+        Err(_e) => Vec::new(),
+    };
+    observations.sort_by_key(|obs| obs.at);
+    observations
+}
+
+pub fn latest_observation(dir: &str, source: &str, ext: &str) -> Option<ObsFile> {
+    list_observations(dir, source, ext).pop()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Rome;
+
+    fn at(rfc3339: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap()
+    }
+
+    #[test]
+    fn obsdatetime_formats() {
+        let utc = utc_offset();
+        assert_eq!(parse_obsdatetime("20260127T192053Z", &utc), Some(at("2026-01-27T19:20:53Z")));
+        assert_eq!(parse_obsdatetime("20260127T202053+0100", &utc), Some(at("2026-01-27T19:20:53Z")));
+        assert_eq!(parse_obsdatetime("20260127T142053-0500", &utc), Some(at("2026-01-27T19:20:53Z")));
+        assert_eq!(parse_obsdatetime("2026-01-27-19-20-53", &utc), Some(at("2026-01-27T19:20:53Z")));
+        assert_eq!(parse_obsdatetime("2026-01-27-20-20-53", &parse_offset("+01:00").unwrap()), Some(at("2026-01-27T19:20:53Z")));
+        assert_eq!(parse_obsdatetime("20260127T192053", &utc), None);
+        assert_eq!(parse_obsdatetime("sources", &utc), None);
+    }
+
+    #[test]
+    fn legacy_names_across_dst() {
+        // Rome is +01:00 in winter and +02:00 in summer
+        assert_eq!(parse_obsdatetime("2026-01-27-20-20-53", &Rome), Some(at("2026-01-27T19:20:53Z")));
+        assert_eq!(parse_obsdatetime("2026-07-27-21-20-53", &Rome), Some(at("2026-07-27T19:20:53Z")));
+        // 02:30 happens twice on 2026-10-25, the first one is still summer time
+        assert_eq!(parse_obsdatetime("2026-10-25-02-30-00", &Rome), Some(at("2026-10-25T00:30:00Z")));
+    }
+
+    #[test]
+    fn file_names() {
+        let utc = utc_offset();
+        let obs = parse_file_name("marex-20260127T192053Z.csv", &utc).unwrap();
+        assert_eq!((obs.source.as_str(), obs.obsdatetime.as_str(), obs.legacy), ("marex", "20260127T192053Z", false));
+
+        let obs = parse_file_name("marex-20260127T142053-0500.csv", &utc).unwrap();
+        assert_eq!((obs.source.as_str(), obs.obsdatetime.as_str()), ("marex", "20260127T142053-0500"));
+        assert_eq!(obs.at, at("2026-01-27T19:20:53Z"));
+
+        let obs = parse_file_name("marex-2026-01-27-19-20-53.csv", &utc).unwrap();
+        assert_eq!((obs.source.as_str(), obs.obsdatetime.as_str(), obs.legacy), ("marex", "2026-01-27-19-20-53", true));
+
+        // sources may contain '-'
+        let obs = parse_file_name("borsa-it-20260127T192053Z.csv", &utc).unwrap();
+        assert_eq!((obs.source.as_str(), obs.obsdatetime.as_str()), ("borsa-it", "20260127T192053Z"));
+        let obs = parse_file_name("borsa-it-20260127T142053-0500.csv", &utc).unwrap();
+        assert_eq!((obs.source.as_str(), obs.obsdatetime.as_str()), ("borsa-it", "20260127T142053-0500"));
+        let obs = parse_file_name("borsa-it-2026-01-27-19-20-53.csv", &utc).unwrap();
+        assert_eq!((obs.source.as_str(), obs.legacy), ("borsa-it", true));
+
+        assert!(parse_file_name("marex-.csv", &utc).is_none());
+        assert!(parse_file_name("-20260127T192053Z.csv", &utc).is_none());
+        assert!(parse_file_name("marex-20260127T192053Z", &utc).is_none());
+    }
+
+    #[test]
+    fn observations_of_a_source() {
+        let dir = std::env::temp_dir().join(format!("naming-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "marex-20260127T192053Z.csv",
+            "marex-2026-01-27-18-00-00.csv",
+            "marex-20260127T150000-0500.csv",
+            "marex-20260127T193000+0100.csv",
+            "marex-20260127T192053Z.json",
+            "marex-it-20260128T000000Z.csv",
+            "marex-notes.csv",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let names = |source| {
+            list_observations(dir.to_str().unwrap(), source, "csv")
+                .into_iter()
+                .map(|obs| obs.file_name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names("marex"),
+            [
+                "marex-2026-01-27-18-00-00.csv",
+                "marex-20260127T193000+0100.csv",
+                "marex-20260127T192053Z.csv",
+                "marex-20260127T150000-0500.csv",
+            ]
+        );
+        assert_eq!(names("marex-it"), ["marex-it-20260128T000000Z.csv"]);
+        assert_eq!(latest_observation(dir.to_str().unwrap(), "marex", "csv").unwrap().file_name, "marex-20260127T150000-0500.csv");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(list_observations(dir.to_str().unwrap(), "marex", "csv").is_empty());
+    }
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use crate::definitions::args::BenchArgs;
+use crate::definitions::config::Config;
+use crate::definitions::types::{Extraction, ExtractionRule, Source};
+use crate::extractors::extract;
+use crate::readers::read_sources_from_file;
+use crate::streaming::StreamScan;
+
+const FILLER: &str = "<div class=\"footer-link\"><a href=\"/legal/notice\" title=\"Legal &amp; notices\">Notes</a></div>\n\
+<script>window.dataLayer = window.dataLayer || []; if (a < b) { dataLayer.push({\"event\": \"view\"}); }</script>\n";
+
+// chunks of about `size` bytes, cut on character boundaries
+fn chunks(page: &str, size: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = page;
+    while !rest.is_empty() {
+        let cut = rest.ceil_char_boundary(size.max(1).min(rest.len()));
+        chunks.push(&rest[..cut]);
+        rest = &rest[cut..];
+    }
+    chunks
+}
+
+// extraction and bytes scanned until the match
+fn stream_extract(source: &Source, chunks: &[&str], rule: &ExtractionRule) -> Result<(Extraction, usize), &'static str> {
+    let mut scan = StreamScan::new(&source.extractor, &source.site, rule).ok_or("rule cannot be streamed")?;
+    let mut scanned = 0;
+    for chunk in chunks {
+        scanned += chunk.len();
+        if scan.feed(chunk) {
+            break;
+        }
+    }
+    Ok((scan.finish()?, scanned))
+}
+
+fn value(extraction: &Result<Extraction, &'static str>) -> String {
+    match extraction {
+        Ok(extraction) => extraction.value.clone(),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+pub fn bench(args: &BenchArgs, source_path: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let sources = read_sources_from_file(source_path).map_err(|e| format!("{}: {}", source_path, e))?;
+    let source = sources
+        .iter()
+        .find(|s| s.site == args.site.trim())
+        .ok_or(format!("site {} not found in {}", args.site, source_path))?;
+    println!("\n--> bench for Source: {:?}, {} iterations", source, args.iterations);
+    let iterations = args.iterations.max(1);
+    let rule = config.extraction_rule(&source.site);
+
+    for page_path in &args.pages {
+        let mut page = fs::read_to_string(page_path)?;
+        if args.pad_kb > 0 {
+            let filler = FILLER.repeat(args.pad_kb * 1024 / FILLER.len() + 1);
+            match page.rfind("</body>") {
+                Some(i) => page.insert_str(i, &filler),
+                None => page.push_str(&filler),
+            }
+        }
+        let chunks = chunks(&page, args.chunk_bytes);
+
+        let start = Instant::now();
+        let mut dom = Err("not run");
+        for _ in 0..iterations {
+            dom = extract(&source.extractor, &page, &source.site, &rule);
+        }
+        let dom_time = start.elapsed() / iterations;
+
+        let start = Instant::now();
+        let mut streamed = Err("not run");
+        for _ in 0..iterations {
+            streamed = stream_extract(source, &chunks, &rule);
+        }
+        let stream_time = start.elapsed() / iterations;
+        let scanned = streamed.as_ref().map(|(_extraction, scanned)| *scanned).unwrap_or(page.len());
+        let streamed = streamed.map(|(extraction, _scanned)| extraction);
+
+        println!("\n----------------------\n{}\n----------------------", page_path);
+        println!("page:    {} bytes in {} chunks", page.len(), chunks.len());
+        println!("dom:     {} in {:?}", value(&dom), dom_time);
+        println!("stream:  {} in {:?}, {} bytes scanned", value(&streamed), stream_time, scanned);
+        // a scan that gives up leaves the page to the DOM extractor, as the fetcher does
+        if streamed.is_err() {
+            println!("> the run falls back to the DOM extractor for this page");
+            continue;
+        }
+        let speedup = dom_time.as_secs_f64() / stream_time.max(Duration::from_nanos(1)).as_secs_f64();
+        println!("speedup: {:.1}x", speedup);
+        if value(&dom) != value(&streamed) {
+            println!("> MISMATCH: the streaming path disagrees with the DOM");
+        }
+    }
+    Ok(())
+}
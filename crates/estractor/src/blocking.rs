@@ -0,0 +1,61 @@
+use regex::Regex;
+
+use crate::definitions::config::Config;
+
+// interstitials served instead of the product page, matched on the page body
+pub const BUILTIN_SIGNATURES: [(&str, &str); 6] = [
+    // challenge and block pages only, product pages may embed the Turnstile widget from challenges.cloudflare.com
+    ("cloudflare challenge", r"(?i)<title>\s*(just a moment\.\.\.|attention required! \| cloudflare)|cf_chl_opt"),
+    ("akamai access denied", r"(?is)<title>\s*access denied\s*</title>.*errors\.edgesuite\.net"),
+    ("datadome captcha", r"(?i)geo\.captcha-delivery\.com|ct\.captcha-delivery\.com"),
+    ("perimeterx captcha", r"(?i)px-captcha|_pxCaptcha"),
+    ("imperva incident", r"(?i)_Incapsula_Resource|Incapsula incident ID"),
+    ("consent wall", r#"(?i)<form[^>]+action="https://consent\.[^"]+""#),
+];
+
+struct Signature {
+    name: String,
+    site: Option<String>, // only pages of this source
+    pattern: Regex,
+}
+
+// recognizes challenge and consent pages so they are reported as blocked, not as parse errors
+pub struct BlockDetector {
+    signatures: Vec<Signature>,
+}
+
+impl BlockDetector {
+    pub fn builtin() -> BlockDetector {
+        BlockDetector::from_config(&Config::default()).expect("builtin signatures compile")
+    }
+
+    pub fn from_config(config: &Config) -> Result<BlockDetector, String> {
+        let mut signatures = Vec::new();
+        if config.blocking.builtin {
+            for (name, pattern) in BUILTIN_SIGNATURES {
+                signatures.push(Signature {
+                    name: name.to_string(),
+                    site: None,
+                    pattern: Regex::new(pattern).map_err(|e| format!("signature {}: {}", name, e))?,
+                });
+            }
+        }
+        for signature in &config.blocking.signatures {
+            signatures.push(Signature {
+                name: signature.name.clone(),
+                site: signature.site.clone(),
+                pattern: Regex::new(&signature.pattern).map_err(|e| format!("signature {}: {}", signature.name, e))?,
+            });
+        }
+        Ok(BlockDetector { signatures })
+    }
+
+    // name of the first signature the page matches
+    pub fn detect(&self, html_content: &str, site: &str) -> Option<&str> {
+        self.signatures
+            .iter()
+            .filter(|signature| signature.site.as_deref().is_none_or(|s| s == site))
+            .find(|signature| signature.pattern.is_match(html_content))
+            .map(|signature| signature.name.as_str())
+    }
+}
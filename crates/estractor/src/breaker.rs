@@ -0,0 +1,59 @@
+use std::sync::Mutex;
+
+use crate::utils::log;
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive: usize,
+    open: Option<String>, // why requests stopped
+}
+
+// stops requests to a source after `max_failures` consecutive failures,
+// one per source and run, so the next run probes the source again
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    site: String,
+    max_failures: usize, // 0 never opens
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(site: &str, max_failures: usize) -> Self {
+        CircuitBreaker {
+            site: site.to_string(),
+            max_failures,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    pub fn open_reason(&self) -> Option<String> {
+        self.state.lock().unwrap().open.clone()
+    }
+
+    pub fn success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open.is_none() {
+            state.consecutive = 0;
+        }
+    }
+
+    // a challenge page will not go away within the run, back off at once
+    pub fn blocked(&self, reason: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive += 1;
+        if self.max_failures > 0 && state.open.is_none() {
+            log!("[BREAKER] {}: circuit open, {}", self.site, reason);
+            state.open = Some(format!("circuit open, {}", reason));
+        }
+    }
+
+    pub fn failure(&self, error: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive += 1;
+        if self.max_failures > 0 && state.consecutive >= self.max_failures && state.open.is_none() {
+            let reason = format!("circuit open after {} consecutive failures, last: {}", state.consecutive, error);
+            log!("[BREAKER] {}: {}", self.site, reason);
+            state.open = Some(reason);
+        }
+    }
+}
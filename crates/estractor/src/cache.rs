@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::definitions::types::ExtractionRule;
+use crate::extractors::strategy;
+use crate::utils::log;
+use crate::writers::write_atomic;
+
+// validators of a product page and the values extracted from it
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub ask: String,
+    pub bid: String,
+    pub currency: String,
+    pub strategy: String,
+    pub fetched_at: String, // when the page was last downloaded
+    #[serde(default)]
+    pub rule: String, // extractor and rule the values were extracted with, see rule_key
+}
+
+// what the cached values depend on besides the page: the extractor and the rule of the
+// source, with the script itself for the script extractor
+pub fn rule_key(extractor: &str, rule: &ExtractionRule) -> String {
+    let rule_text = strategy(rule.rule.as_deref().unwrap_or("builtin"), rule.attribute.as_deref());
+    match &rule.script {
+        Some(script) => {
+            let digest = Sha256::digest(script.code.as_bytes());
+            format!("{} {} {} {:x}", extractor, rule_text, script.path, digest)
+        }
+        None => format!("{} {}", extractor, rule_text),
+    }
+}
+
+// ETag and Last-Modified per source and url, loaded at the start of a run and saved at its end;
+// one file per run lock, so that no two runs write the same one
+pub struct HttpCache {
+    path: String,
+    entries: Mutex<HashMap<String, CacheEntry>>, // by "site url"
+}
+
+// the cached values come from the extractor of the source, two sources may fetch the same url
+fn key(site: &str, url: &str) -> String {
+    format!("{} {}", site, url)
+}
+
+impl HttpCache {
+    // a missing or unreadable file starts an empty cache, every page is downloaded again
+    pub fn load(path: &str) -> HttpCache {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                eprintln!("[CACHE] {}: {}, starting empty", path, e);
+                HashMap::new()
+            }),
+            Err(_e) => HashMap::new(),
+        };
+        HttpCache {
+            path: path.to_string(),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    // entries extracted with another extractor or rule are dropped, their values may be wrong
+    pub fn get(&self, site: &str, url: &str, rule: &str) -> Option<CacheEntry> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = key(site, url);
+        match entries.get(&key) {
+            Some(entry) if entry.rule != rule => {
+                log!("[CACHE] {}: extracted with {:?}, now {:?}, dropped", key, entry.rule, rule);
+                entries.remove(&key);
+                None
+            }
+            entry => entry.cloned(),
+        }
+    }
+
+    pub fn insert(&self, site: &str, url: &str, entry: CacheEntry) {
+        self.entries.lock().unwrap().insert(key(site, url), entry);
+    }
+
+    // pages that stopped sending validators are no longer asked conditionally
+    pub fn remove(&self, site: &str, url: &str) {
+        self.entries.lock().unwrap().remove(&key(site, url));
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let entries = self.entries.lock().unwrap();
+        let content = serde_json::to_string_pretty(&*entries).map_err(|e| e.to_string())?;
+        write_atomic(&self.path, content.as_bytes()).map_err(|e| format!("{}: {}", self.path, e))?;
+        log!("[CACHE] {} entries saved to {}", entries.len(), self.path);
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::definitions::config::{Config, VenueConfig};
+use crate::readers::read_block_from_file;
+
+pub const IN_SESSION: &str = "in-session";
+pub const OFF_SESSION: &str = "off-session";
+
+#[derive(Debug, Clone)]
+pub struct Venue {
+    pub name: String,
+    tz: Tz,
+    open: NaiveTime,
+    close: NaiveTime,
+    weekdays: Vec<Weekday>,
+    holidays: HashSet<NaiveDate>,
+}
+
+impl Venue {
+    pub fn from_config(name: &str, venue: &VenueConfig) -> Result<Venue, String> {
+        let tz: Tz = venue
+            .timezone
+            .parse()
+            .map_err(|_e| format!("venue {}: unknown timezone {}", name, venue.timezone))?;
+        let time = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|e| format!("venue {}: invalid time {}: {}", name, t, e))
+        };
+        let weekdays = venue
+            .weekdays
+            .iter()
+            .map(|d| d.parse::<Weekday>().map_err(|_e| format!("venue {}: invalid weekday {}", name, d)))
+            .collect::<Result<Vec<Weekday>, String>>()?;
+        let mut holidays = HashSet::new();
+        if let Some(holidays_path) = &venue.holidays {
+            let lines = read_block_from_file(holidays_path).map_err(|e| format!("venue {}: {}: {}", name, holidays_path, e))?;
+            for line in lines {
+                // "YYYY-MM-DD, description"
+                let date = line.split(",").next().unwrap_or("").trim();
+                let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|e| format!("venue {}: invalid holiday {}: {}", name, line, e))?;
+                holidays.insert(date);
+            }
+        }
+        Ok(Venue {
+            name: name.to_string(),
+            tz,
+            open: time(&venue.open)?,
+            close: time(&venue.close)?,
+            weekdays,
+            holidays,
+        })
+    }
+
+    pub fn in_session(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.tz);
+        self.weekdays.contains(&local.weekday())
+            && !self.holidays.contains(&local.date_naive())
+            && local.time() >= self.open
+            && local.time() < self.close
+    }
+}
+
+// venues by name and the venue of each site
+#[derive(Debug, Clone, Default)]
+pub struct Calendar {
+    venues: HashMap<String, Venue>,
+    sites: HashMap<String, String>,
+}
+
+impl Calendar {
+    pub fn from_config(config: &Config) -> Result<Calendar, String> {
+        let mut venues = HashMap::new();
+        for (name, venue) in &config.venues {
+            venues.insert(name.clone(), Venue::from_config(name, venue)?);
+        }
+        let mut sites = HashMap::new();
+        for (site, source) in &config.sources {
+            if let Some(venue) = &source.venue {
+                if !venues.contains_key(venue) {
+                    return Err(format!("source {}: unknown venue {}", site, venue));
+                }
+                sites.insert(site.clone(), venue.clone());
+            }
+        }
+        Ok(Calendar { venues, sites })
+    }
+
+    pub fn venue(&self, site: &str) -> Option<&Venue> {
+        self.sites.get(site).and_then(|venue| self.venues.get(venue))
+    }
+
+    // None when the site has no venue, it is then always considered open
+    pub fn in_session(&self, site: &str, at: DateTime<Utc>) -> Option<bool> {
+        self.venue(site).map(|venue| venue.in_session(at))
+    }
+
+    pub fn session_tag(&self, site: &str, at: DateTime<Utc>) -> Option<String> {
+        self.in_session(site, at)
+            .map(|open| if open { IN_SESSION } else { OFF_SESSION }.to_string())
+    }
+}
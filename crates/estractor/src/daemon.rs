@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use serde::Serialize;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::task::{Id, JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use crate::config_error;
+use crate::calendar::Calendar;
+use crate::definitions::args::{Args, DaemonArgs};
+use crate::definitions::config::Config;
+use crate::definitions::globals::DAEMON_STATUS_FILE;
+use crate::definitions::types::{Paths, RunSlot, Source, SourceReport};
+use crate::outcome::source_issue;
+use crate::extractors::check_rules;
+use crate::readers::{read_config_from_file, read_sources_from_file};
+use crate::runner;
+use crate::signals;
+use crate::utils::log;
+use crate::writers::write_atomic;
+
+// cron schedule of one source
+struct Job {
+    source: Source,
+    schedule: Schedule,
+    next: Option<DateTime<Utc>>,
+}
+
+// config, sources and schedules, reloaded on SIGHUP
+struct Loaded {
+    config: Arc<Config>,
+    calendar: Arc<Calendar>,
+    tz: Tz,
+    jobs: Vec<Job>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct JobStatus {
+    schedule: String,
+    next_run: Option<String>,
+    running: bool,
+    runs: usize,
+    overlaps: usize, // runs skipped because the previous one had not finished
+    last_started: Option<String>,
+    last_finished: Option<String>,
+    last_result: Option<String>, // ok, partial: <issue>, skipped or failed: <error>
+    last_quotes: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DaemonStatus {
+    pid: u32,
+    state: String, // running or stopped
+    started_at: String,
+    updated_at: String,
+    config_path: String,
+    reloaded_at: Option<String>,
+    reload_error: Option<String>,
+    sources: BTreeMap<String, JobStatus>,
+}
+
+type SharedStatus = Arc<Mutex<DaemonStatus>>;
+
+// cron expressions have seconds, the usual five fields are accepted too
+fn parse_schedule(expr: &str) -> Result<Schedule, String> {
+    let expr = expr.trim();
+    let expr = if expr.split_whitespace().count() == 5 { format!("0 {}", expr) } else { expr.to_string() };
+    Schedule::from_str(&expr).map_err(|e| format!("invalid schedule {}: {}", expr, e))
+}
+
+fn next_run(schedule: &Schedule, tz: Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    schedule.after(&after.with_timezone(&tz)).next().map(|at| at.with_timezone(&Utc))
+}
+
+fn load(paths: &Paths) -> Result<Loaded, String> {
+    let config = read_config_from_file(&paths.config_path).map_err(|e| e.to_string())?;
+    config.validate()?;
+    let calendar = Calendar::from_config(&config)?;
+    let tz: Tz = config
+        .daemon
+        .timezone
+        .parse()
+        .map_err(|_e| format!("daemon: unknown timezone {}", config.daemon.timezone))?;
+    let sources = read_sources_from_file(&paths.source_path).map_err(|e| format!("{}: {}", paths.source_path, e))?;
+    check_rules(&sources, &config)?;
+    let now = Utc::now();
+    let mut jobs = Vec::new();
+    for (site, source_config) in &config.sources {
+        let Some(expr) = &source_config.schedule else {
+            continue;
+        };
+        let schedule = parse_schedule(expr).map_err(|e| format!("source {}: {}", site, e))?;
+        let Some(source) = sources.iter().find(|s| &s.site == site) else {
+            eprintln!("[DAEMON] {} has a schedule but is not in {}", site, paths.source_path);
+            continue;
+        };
+        jobs.push(Job {
+            source: source.clone(),
+            next: next_run(&schedule, tz, now),
+            schedule,
+        });
+    }
+    jobs.sort_by(|a, b| a.source.site.cmp(&b.source.site));
+    Ok(Loaded {
+        config: Arc::new(config),
+        calendar: Arc::new(calendar),
+        tz,
+        jobs,
+    })
+}
+
+fn write_status(status_path: &str, status: &SharedStatus) {
+    let content = {
+        let mut status = status.lock().unwrap();
+        status.updated_at = Utc::now().to_rfc3339();
+        serde_json::to_string_pretty(&*status)
+    };
+    let written = content.map_err(|e| e.to_string()).and_then(|c| write_atomic(status_path, c.as_bytes()).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("[DAEMON] cannot write status {}: {}", status_path, e);
+    }
+}
+
+// schedules of the loaded jobs, sources no longer scheduled are dropped unless running
+fn refresh_status(status: &SharedStatus, loaded: &Loaded) {
+    let mut status = status.lock().unwrap();
+    status.sources.retain(|site, job| job.running || loaded.jobs.iter().any(|j| &j.source.site == site));
+    for job in &loaded.jobs {
+        let entry = status.sources.entry(job.source.site.clone()).or_default();
+        entry.schedule = job.schedule.source().to_string();
+        entry.next_run = job.next.map(|at| at.to_rfc3339());
+    }
+}
+
+fn finish_run(status: &SharedStatus, config: &Config, sites: &[String], result: &Result<Vec<SourceReport>, String>) {
+    let finished_at = Utc::now().to_rfc3339();
+    let mut status = status.lock().unwrap();
+    for site in sites {
+        let Some(entry) = status.sources.get_mut(site) else {
+            continue;
+        };
+        let report = result.as_ref().ok().and_then(|reports| reports.iter().find(|r| &r.site == site));
+        entry.running = false;
+        entry.last_finished = Some(finished_at.clone());
+        entry.last_quotes = report.map(|r| r.quotes).unwrap_or(0);
+        entry.last_result = Some(match (result, report) {
+            (Err(e), _) => format!("failed: {}", e),
+            (Ok(_), Some(SourceReport { error: Some(error), .. })) => format!("failed: {}", error),
+            (Ok(_), Some(report)) if report.isins > 0 && report.quotes == 0 => "failed: no quotes".to_string(),
+            (Ok(_), Some(report)) => match source_issue(report, config) {
+                Some(issue) => format!("partial: {}", issue),
+                None => "ok".to_string(),
+            },
+            (Ok(_), None) => "skipped".to_string(), // locked by another run
+        });
+    }
+}
+
+// a run task that panicked never reached finish_run, its sources would stay running for good
+fn run_joined(
+    joined: Result<Id, JoinError>,
+    run_sites: &mut HashMap<Id, Vec<String>>,
+    status: &SharedStatus,
+    config: &Config,
+    status_path: &str,
+) {
+    let id = match &joined {
+        Ok(id) => *id,
+        Err(e) => e.id(),
+    };
+    let Some(sites) = run_sites.remove(&id) else {
+        return;
+    };
+    if let Err(e) = joined {
+        eprintln!("[DAEMON] run of {} panicked: {}", sites.join(", "), e);
+        finish_run(status, config, &sites, &Err(format!("panicked: {}", e)));
+        write_status(status_path, status);
+    }
+}
+
+// run the sources on their schedules until SIGTERM or ctrl-c, a source still running
+// when it is due again is skipped for that tick
+pub async fn daemon(args: &Args, daemon_args: &DaemonArgs, paths: &Paths) -> Result<(), Box<dyn Error>> {
+    let status_path = match &daemon_args.status_file {
+        Some(status_file) => status_file.clone(),
+        None => [paths.output_path_prefix.as_str(), DAEMON_STATUS_FILE].concat(),
+    };
+    let _ = std::fs::create_dir_all(&paths.output_path_prefix);
+    // at startup there are no previous settings to keep, as on a reload
+    let mut loaded = load(paths).unwrap_or_else(|e| config_error(e));
+    let status: SharedStatus = Arc::new(Mutex::new(DaemonStatus {
+        pid: std::process::id(),
+        state: "running".to_string(),
+        started_at: Utc::now().to_rfc3339(),
+        updated_at: String::new(),
+        config_path: paths.config_path.clone(),
+        reloaded_at: None,
+        reload_error: None,
+        sources: BTreeMap::new(),
+    }));
+    refresh_status(&status, &loaded);
+    write_status(&status_path, &status);
+    log!("[DAEMON] {} scheduled sources, status in {}", loaded.jobs.len(), status_path);
+
+    let args = Arc::new(args.clone());
+    let paths = Arc::new(paths.clone());
+    let shutdown = CancellationToken::new();
+    let mut sighup = signal(SignalKind::hangup())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut runs = JoinSet::new();
+    let mut run_sites = HashMap::new(); // by run task
+    loop {
+        let now = Utc::now();
+        let wait = match loaded.jobs.iter().filter_map(|job| job.next).min() {
+            Some(next) => (next - now).to_std().unwrap_or(Duration::ZERO),
+            None => Duration::from_secs(3600),
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = sighup.recv() => {
+                log!("[DAEMON] SIGHUP received, reloading {}", paths.config_path);
+                match load(&paths) {
+                    Ok(reloaded) => {
+                        loaded = reloaded;
+                        let mut status = status.lock().unwrap();
+                        status.reloaded_at = Some(Utc::now().to_rfc3339());
+                        status.reload_error = None;
+                    }
+                    Err(e) => {
+                        eprintln!("[DAEMON] reload failed, keeping the previous config: {}", e);
+                        status.lock().unwrap().reload_error = Some(e);
+                    }
+                }
+                refresh_status(&status, &loaded);
+                write_status(&status_path, &status);
+                continue;
+            }
+            _ = sigterm.recv() => break,
+            _ = tokio::signal::ctrl_c() => break,
+            Some(joined) = runs.join_next_with_id(), if !runs.is_empty() => {
+                run_joined(joined.map(|(id, ())| id), &mut run_sites, &status, &loaded.config, &status_path);
+                continue;
+            }
+        }
+
+        // due sources start together as one run, overlapping ones wait for their next tick
+        let now = Utc::now();
+        let mut due = Vec::new();
+        for job in loaded.jobs.iter_mut().filter(|job| job.next.is_some_and(|next| next <= now)) {
+            job.next = next_run(&job.schedule, loaded.tz, now);
+            let mut status = status.lock().unwrap();
+            let entry = status.sources.entry(job.source.site.clone()).or_default();
+            if entry.running {
+                log!("[DAEMON] {} is still running, skipping this run", job.source.site);
+                entry.overlaps += 1;
+                continue;
+            }
+            entry.running = true;
+            entry.runs += 1;
+            entry.last_started = Some(now.to_rfc3339());
+            due.push(job.source.clone());
+        }
+        refresh_status(&status, &loaded);
+        write_status(&status_path, &status);
+        if due.is_empty() {
+            continue;
+        }
+        let sites: Vec<String> = due.iter().map(|source| source.site.clone()).collect();
+        log!("[DAEMON] running {}", sites.join(", "));
+        let (args, paths, status, status_path) = (args.clone(), paths.clone(), status.clone(), status_path.clone());
+        let (config, calendar) = (loaded.config.clone(), loaded.calendar.clone());
+        let cancel = shutdown.child_token();
+        let spawned_sites = sites.clone();
+        let run = runs.spawn(async move {
+            signals::watch_deadline(cancel.clone(), args.deadline.map(Duration::from_secs));
+            let result = runner::run_sources(&args, &paths, &config, &calendar, due, &RunSlot::default(), cancel.clone()).await;
+            cancel.cancel(); // stops the deadline watch
+            if let Err(e) = &result {
+                eprintln!("[DAEMON] run of {} failed: {}", sites.join(", "), e);
+            }
+            finish_run(&status, &config, &sites, &result);
+            write_status(&status_path, &status);
+        });
+        run_sites.insert(run.id(), spawned_sites);
+    }
+
+    // pending requests are cancelled, the runs still write what they collected
+    log!("[DAEMON] stopping, waiting for {} runs", runs.len());
+    shutdown.cancel();
+    while let Some(joined) = runs.join_next_with_id().await {
+        run_joined(joined.map(|(id, ())| id), &mut run_sites, &status, &loaded.config, &status_path);
+    }
+    status.lock().unwrap().state = "stopped".to_string();
+    write_status(&status_path, &status);
+    Ok(())
+}
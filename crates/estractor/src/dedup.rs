@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use common::markers::resolve_unchanged;
+use common::naming::list_observations;
+
+// isin -> (ask, bid, currency)
+type Prices = HashMap<String, (String, String, String)>;
+
+// provenance columns and marker lines are ignored
+fn read_prices(path: &Path) -> Result<Prices, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+    let mut prices = HashMap::new();
+    for record in rdr.records() {
+        let record = record?;
+        if record.len() < 5 || record[0].starts_with("-- ") {
+            continue;
+        }
+        prices.insert(
+            record[0].to_string(),
+            (record[2].to_string(), record[3].to_string(), record[4].to_string()),
+        );
+    }
+    Ok(prices)
+}
+
+pub struct Comparison {
+    pub previous: String, // file name of the latest stored observation
+    pub changed: usize,
+}
+
+impl Comparison {
+    pub fn unchanged(&self) -> bool {
+        self.changed == 0
+    }
+}
+
+// compare a new observation with the latest stored one of the same source,
+// quotes that moved, appeared or disappeared all count as changed
+pub fn compare_with_latest(new_path: &str, output_filepath: &str, site: &str) -> Option<Comparison> {
+    let dir = Path::new(output_filepath).parent()?;
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    // an earlier attempt of the same slot has the same name, it is not a previous observation
+    let file_name = Path::new(output_filepath).file_name()?.to_str()?;
+    let latest = list_observations(dir.to_str()?, site, "csv")
+        .into_iter()
+        .rfind(|obs| obs.file_name != file_name)?;
+    let previous_path = resolve_unchanged(&dir.join(&latest.file_name));
+    let previous = match read_prices(&previous_path) {
+        Ok(previous) => previous,
+        Err(e) => {
+            eprintln!("[DEDUP] cannot read {}: {}", previous_path.display(), e);
+            return None;
+        }
+    };
+    let new = read_prices(Path::new(new_path)).ok()?;
+    let moved = new.iter().filter(|(isin, price)| previous.get(*isin) != Some(price)).count();
+    let gone = previous.keys().filter(|isin| !new.contains_key(*isin)).count();
+    Some(Comparison {
+        previous: previous_path.file_name()?.to_string_lossy().to_string(),
+        changed: moved + gone,
+    })
+}
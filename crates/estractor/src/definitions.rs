@@ -1,3 +1,4 @@
 pub mod globals;
 pub mod types;
-pub mod args; // clap arguments
\ No newline at end of file
+pub mod args; // clap arguments
+pub mod config; // settings file
\ No newline at end of file
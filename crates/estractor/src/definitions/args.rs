@@ -1,26 +1,176 @@
 use clap::*;
-use crate::definitions::globals::*; 
+use serde::Serialize;
+use crate::definitions::globals::*;
 /*
 pub const ISIN_PATH_PREFIX: &str = "data/";
 pub const OUTPUT_PATH_PREFIX: &str = "data/output/";
-pub const SOURCE_PATH: &str = "data/sources.txt"; 
+pub const SOURCE_PATH: &str = "data/sources.txt";
 */
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone, Serialize)]
 #[command(version, about = "Digital Posture RWS", long_about = None)]
 pub struct Args {
-    /// Source file path 
+    /// Source file path
     #[arg(short, long, default_value = SOURCE_PATH)]
     pub source_fp: String,
 
-    /// Source file path 
+    /// Source file path
     #[arg(short, long, default_value = ISIN_PATH_PREFIX)]
     pub isin_fp_prefix: String,
 
-    /// Output file path 
+    /// Output file path
     #[arg(short, long, default_value = OUTPUT_PATH_PREFIX)]
     pub output_fp_prefix: String,
 
+    /// Settings file (toml): daemon, thresholds, breaker, blocking, http, extraction, venues and per-source settings
+    #[arg(short, long, global = true, default_value = CONFIG_PATH)]
+    pub config: String,
+
+    /// Mixed watchlist file, ISINs are routed to their source instead of read per site
+    #[arg(short, long)]
+    pub watchlist: Option<String>,
+
     /// Output format
-    #[arg(short = 'f', long, default_value = "csv")]
+    #[arg(short = 'f', long, global = true, default_value = "csv", value_parser = ["csv", "jsonl"])]
     pub output_format: String,
-}
\ No newline at end of file
+
+    /// Maximum concurrent requests per host
+    #[arg(long, global = true, default_value_t = MAX_PER_HOST)]
+    pub max_per_host: usize,
+
+    /// Add provenance columns (fetched_at, url, http_status, extractor, strategy, run_id, not_modified)
+    #[arg(long, global = true)]
+    pub provenance: bool,
+
+    /// Output file naming: utc, an explicit offset such as +01:00, or legacy
+    #[arg(long, global = true, default_value = NAMING_SCHEME, allow_hyphen_values = true)]
+    pub naming: String,
+
+    /// When no quote moved since the latest observation: write it anyway, skip it, or write an unchanged marker
+    #[arg(long, default_value = UNCHANGED_MODE, value_parser = ["write", "skip", "marker"])]
+    pub unchanged: String,
+
+    /// Outside the trading hours of a source venue: run anyway, skip the source, or tag each quote with its session
+    #[arg(long, global = true, default_value = SESSIONS_MODE, value_parser = ["ignore", "skip", "tag"])]
+    pub sessions: String,
+
+    /// When another run holds the output directory lock: wait for it, skip this run, or fail
+    #[arg(long, global = true, default_value = LOCK_MODE, value_parser = ["wait", "skip", "fail"])]
+    pub lock: String,
+
+    /// Lock lease in seconds, a crashed run releases the lock after this long
+    #[arg(long, global = true, default_value_t = LOCK_TTL)]
+    pub lock_ttl: u64,
+
+    /// Longest wait for the lock in seconds, then the run fails
+    #[arg(long, global = true, default_value_t = LOCK_WAIT)]
+    pub lock_wait: u64,
+
+    /// Schedule slot id, defaults to CLOUD_RUN_EXECUTION; a retry of the slot replaces its output
+    #[arg(long)]
+    pub slot: Option<String>,
+
+    /// Index of this task among the parallel tasks, defaults to CLOUD_RUN_TASK_INDEX
+    #[arg(long)]
+    pub task_index: Option<usize>,
+
+    /// Number of parallel tasks sharing the ISINs, defaults to CLOUD_RUN_TASK_COUNT
+    #[arg(long)]
+    pub task_count: Option<usize>,
+
+    /// Stop after this many seconds and write the quotes collected so far
+    #[arg(long)]
+    pub deadline: Option<u64>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone, Serialize)]
+pub enum Command {
+    /// Test one ISIN against one source and print what each extractor finds
+    Probe(ProbeArgs),
+    /// Quote ad-hoc ISINs and stream the results to stdout
+    Quote(QuoteArgs),
+    /// Time the DOM and the streaming extraction of a source over saved pages
+    Bench(BenchArgs),
+    /// Rename output files from the legacy local-time names to the naming scheme
+    MigrateNames(MigrateArgs),
+    /// Merge the shard outputs of sharded runs that were not merged by their last task
+    MergeShards,
+    /// Run the sources on the cron schedules of the config file until stopped
+    Daemon(DaemonArgs),
+}
+
+#[derive(clap::Args, Debug, Clone, Serialize)]
+pub struct ProbeArgs {
+    /// Site as written in the source file
+    #[arg(long)]
+    pub site: String,
+
+    /// ISIN appended to the site base url
+    #[arg(long)]
+    pub isin: String,
+
+    /// Read the page from a saved html file instead of fetching it
+    #[arg(long)]
+    pub from_file: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, Serialize)]
+pub struct BenchArgs {
+    /// Site as written in the source file
+    #[arg(long)]
+    pub site: String,
+
+    /// Saved html pages of the site, e.g. from a browser or curl
+    #[arg(required = true)]
+    pub pages: Vec<String>,
+
+    /// Extractions per page and path
+    #[arg(long, default_value_t = 100)]
+    pub iterations: u32,
+
+    /// Size of the chunks fed to the streaming path, as they would arrive from the network
+    #[arg(long, default_value_t = 16384)]
+    pub chunk_bytes: usize,
+
+    /// Filler markup added before </body>, in KB, to mimic the scripts and footers of issuer pages
+    #[arg(long, default_value_t = 0)]
+    pub pad_kb: usize,
+}
+
+#[derive(clap::Args, Debug, Clone, Serialize)]
+pub struct QuoteArgs {
+    /// Site as written in the source file
+    #[arg(long)]
+    pub site: String,
+
+    /// ISINs to quote
+    pub isins: Vec<String>,
+
+    /// Read more ISINs from a file, one per line ("-" for stdin)
+    #[arg(long = "isins")]
+    pub isins_from: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Clone, Serialize)]
+pub struct MigrateArgs {
+    /// Directory to migrate, defaults to the output path
+    #[arg(long)]
+    pub dir: Option<String>,
+
+    /// Timezone the legacy names were written in, an IANA name (Europe/Rome) or a fixed offset (+01:00)
+    #[arg(long, default_value = "UTC", allow_hyphen_values = true)]
+    pub legacy_tz: String,
+
+    /// Only print the renames
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(clap::Args, Debug, Clone, Serialize)]
+pub struct DaemonArgs {
+    /// Status file, rewritten whenever a run starts or ends, defaults to the output path
+    #[arg(long)]
+    pub status_file: Option<String>,
+}
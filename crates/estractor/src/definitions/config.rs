@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::blocking::BlockDetector;
+use crate::definitions::globals::REDACTED;
+use crate::definitions::types::{ExtractionRule, Script};
+use crate::http;
+
+// optional settings file (toml), sources themselves stay in the sources file
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub daemon: DaemonConfig,
+    pub thresholds: Thresholds,
+    pub breaker: Breaker,
+    pub blocking: Blocking,
+    pub http: HttpConfig,
+    pub extraction: ExtractionConfig,
+    pub venues: HashMap<String, VenueConfig>,
+    pub sources: HashMap<String, SourceConfig>, // by site
+}
+
+// trading venue, e.g. SeDeX or EuroTLX
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VenueConfig {
+    pub timezone: String, // IANA name
+    pub open: String,     // HH:MM local time
+    pub close: String,
+    pub weekdays: Vec<String>,
+    pub holidays: Option<String>, // file with one date per line between START and END
+}
+
+impl Default for VenueConfig {
+    fn default() -> Self {
+        VenueConfig {
+            timezone: "Europe/Rome".to_string(),
+            open: "09:00".to_string(),
+            close: "17:30".to_string(),
+            weekdays: ["Mon", "Tue", "Wed", "Thu", "Fri"].iter().map(|d| d.to_string()).collect(),
+            holidays: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SourceConfig {
+    pub venue: Option<String>,
+    pub max_failure_rate: Option<f64>, // overrides thresholds.source
+    pub max_consecutive_failures: Option<usize>, // overrides breaker.max_consecutive_failures
+    pub schedule: Option<String>, // cron expression, run by the daemon
+    pub session: Option<SessionConfig>,
+    pub http: Option<HttpOverrides>,
+    pub streaming: Option<bool>, // overrides extraction.streaming
+    pub rule: Option<String>,      // replaces the builtin rule of the site, for its extractor
+    pub attribute: Option<String>, // selector and xpath: read this attribute instead of the text
+    pub script: Option<String>,    // path of the script run by the script extractor
+}
+
+// cookie store of one source, set up before its first ISIN request
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub preflight: Vec<String>, // urls fetched first, e.g. the landing page
+    pub headers: HashMap<String, String>,
+    pub cookies: HashMap<String, String>,     // name -> value
+    pub cookies_env: HashMap<String, String>, // name -> environment variable holding the value
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    pub timezone: String, // schedules are read in this timezone
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            timezone: "UTC".to_string(),
+        }
+    }
+}
+
+// largest share of ISINs that may fail, 0.2 is 20%
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Thresholds {
+    pub run: f64,    // above it the run fails
+    pub source: f64, // above it the source, and so the run, is partial
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds { run: 1.0, source: 1.0 }
+    }
+}
+
+// consecutive failed requests after which a source is given up for the run, 0 never
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Breaker {
+    pub max_consecutive_failures: usize,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Breaker { max_consecutive_failures: 5 }
+    }
+}
+
+// streaming scans the page as it downloads, without a DOM, and stops at the price;
+// rules it cannot match (e.g. selectors with combinators) keep the DOM extractor
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ExtractionConfig {
+    pub streaming: bool,
+    pub script_max_operations: u64, // a script past this many operations fails, e.g. an endless loop
+}
+
+impl Default for ExtractionConfig {
+    fn default() -> Self {
+        ExtractionConfig {
+            streaming: false,
+            script_max_operations: 1_000_000,
+        }
+    }
+}
+
+// challenge and consent page signatures, on top of the builtin ones
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Blocking {
+    pub builtin: bool,
+    pub signatures: Vec<SignatureConfig>,
+}
+
+impl Default for Blocking {
+    fn default() -> Self {
+        Blocking {
+            builtin: true,
+            signatures: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SignatureConfig {
+    pub name: String,
+    pub pattern: String, // regex on the page body
+    #[serde(default)]
+    pub site: Option<String>, // all sources when unset
+}
+
+// http client of every request, a hung connection must not hold the run
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64, // between two reads of the response
+    pub timeout_secs: u64,      // whole request, body included
+    pub proxy: Option<String>,  // e.g. http://proxy:3128, all schemes
+    pub ca_bundle: Option<String>, // pem file with extra root certificates
+    pub max_response_bytes: usize, // decoded body, 0 is no limit
+    pub gzip: bool,
+    pub brotli: bool,
+    pub http2: bool, // false sticks to HTTP/1.1
+    pub conditional: bool, // If-None-Match / If-Modified-Since from the http cache
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            timeout_secs: 60,
+            proxy: None,
+            ca_bundle: None,
+            max_response_bytes: 5_000_000,
+            gzip: true,
+            brotli: true,
+            http2: true,
+            conditional: true,
+        }
+    }
+}
+
+// per source, unset fields keep the [http] value
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpOverrides {
+    pub connect_timeout_secs: Option<u64>,
+    pub read_timeout_secs: Option<u64>,
+    pub timeout_secs: Option<u64>,
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<String>,
+    pub max_response_bytes: Option<usize>,
+    pub gzip: Option<bool>,
+    pub brotli: Option<bool>,
+    pub http2: Option<bool>,
+    pub conditional: Option<bool>,
+}
+
+impl HttpConfig {
+    fn with_overrides(&self, overrides: &HttpOverrides) -> HttpConfig {
+        HttpConfig {
+            connect_timeout_secs: overrides.connect_timeout_secs.unwrap_or(self.connect_timeout_secs),
+            read_timeout_secs: overrides.read_timeout_secs.unwrap_or(self.read_timeout_secs),
+            timeout_secs: overrides.timeout_secs.unwrap_or(self.timeout_secs),
+            proxy: overrides.proxy.clone().or_else(|| self.proxy.clone()),
+            ca_bundle: overrides.ca_bundle.clone().or_else(|| self.ca_bundle.clone()),
+            max_response_bytes: overrides.max_response_bytes.unwrap_or(self.max_response_bytes),
+            gzip: overrides.gzip.unwrap_or(self.gzip),
+            brotli: overrides.brotli.unwrap_or(self.brotli),
+            http2: overrides.http2.unwrap_or(self.http2),
+            conditional: overrides.conditional.unwrap_or(self.conditional),
+        }
+    }
+}
+
+// credentials of a proxy url, one that does not parse is redacted whole
+fn redact_url(url: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return REDACTED.to_string();
+    };
+    if !parsed.username().is_empty() {
+        let _ = parsed.set_username(REDACTED);
+    }
+    if parsed.password().is_some() {
+        let _ = parsed.set_password(Some(REDACTED));
+    }
+    parsed.to_string()
+}
+
+impl Config {
+    // settings as written to the manifest, without the secrets they may hold
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        config.http.proxy = config.http.proxy.as_deref().map(redact_url);
+        for source in config.sources.values_mut() {
+            if let Some(session) = source.session.as_mut() {
+                session.headers.values_mut().for_each(|value| *value = REDACTED.to_string());
+                session.cookies.values_mut().for_each(|value| *value = REDACTED.to_string());
+            }
+            if let Some(http) = source.http.as_mut() {
+                http.proxy = http.proxy.as_deref().map(redact_url);
+            }
+        }
+        config
+    }
+
+    // [http] with the overrides of the source
+    pub fn http(&self, site: &str) -> HttpConfig {
+        match self.sources.get(site).and_then(|source| source.http.as_ref()) {
+            Some(overrides) => self.http.with_overrides(overrides),
+            None => self.http.clone(),
+        }
+    }
+
+    pub fn session(&self, site: &str) -> Option<&SessionConfig> {
+        self.sources.get(site).and_then(|source| source.session.as_ref())
+    }
+
+    pub fn extraction_rule(&self, site: &str) -> ExtractionRule {
+        match self.sources.get(site) {
+            Some(source) => ExtractionRule {
+                rule: source.rule.clone(),
+                attribute: source.attribute.clone(),
+                script: source.script.as_ref().and_then(|path| self.script(path)),
+            },
+            None => ExtractionRule::default(),
+        }
+    }
+
+    // read on every run, so an edited script needs no restart; a missing one is logged
+    fn script(&self, path: &str) -> Option<Script> {
+        match fs::read_to_string(path) {
+            Ok(code) => Some(Script {
+                path: path.to_string(),
+                code,
+                max_operations: self.extraction.script_max_operations,
+            }),
+            Err(e) => {
+                eprintln!("[SCRIPT] {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    pub fn streaming(&self, site: &str) -> bool {
+        self.sources
+            .get(site)
+            .and_then(|source| source.streaming)
+            .unwrap_or(self.extraction.streaming)
+    }
+
+    pub fn breaker_threshold(&self, site: &str) -> usize {
+        self.sources
+            .get(site)
+            .and_then(|source| source.max_consecutive_failures)
+            .unwrap_or(self.breaker.max_consecutive_failures)
+    }
+
+    pub fn source_threshold(&self, site: &str) -> f64 {
+        self.sources
+            .get(site)
+            .and_then(|source| source.max_failure_rate)
+            .unwrap_or(self.thresholds.source)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let rates = [("thresholds.run".to_string(), self.thresholds.run), ("thresholds.source".to_string(), self.thresholds.source)];
+        let source_rates = self
+            .sources
+            .iter()
+            .filter_map(|(site, source)| Some((format!("sources.{}.max_failure_rate", site), source.max_failure_rate?)));
+        for (name, rate) in rates.into_iter().chain(source_rates) {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!("{} must be between 0 and 1, got {}", name, rate));
+            }
+        }
+        BlockDetector::from_config(self)?;
+        // proxy urls and CA bundles are checked by building the clients
+        http::client_builder(&self.http)?.build().map_err(|e| format!("http: {}", e))?;
+        for site in self.sources.keys().filter(|site| self.sources[*site].http.is_some()) {
+            http::client_builder(&self.http(site))?
+                .build()
+                .map_err(|e| format!("sources.{}.http: {}", site, e))?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,40 @@
 // const
 pub const DEF_PRICE: &str = "0.00";
+// quotes buffered between the fetch tasks and the file writer
+pub const QUOTE_CHANNEL_SIZE: usize = 64;
+// exit codes
+pub const EXIT_FAILURE: i32 = 1; // nothing quoted, too many failures, or a write error
+pub const EXIT_CONFIG: i32 = 2; // invalid arguments or settings, same as clap
+pub const EXIT_PARTIAL: i32 = 3;
 // filepaths
 pub const ISIN_PATH_PREFIX: &str = "data/";
 pub const OUTPUT_PATH_PREFIX: &str = "data/output/";
 pub const SOURCE_PATH: &str = "data/sources.txt";
+pub const CONFIG_PATH: &str = "data/estractor.toml";
+pub const NAMING_SCHEME: &str = "utc"; // see common::naming
+pub const UNCHANGED_MODE: &str = "write"; // write, skip or marker
+pub const SESSIONS_MODE: &str = "ignore"; // ignore, skip or tag
+// http
+pub const MAX_PER_HOST: usize = 4;
+pub const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.36";
+pub const LOCK_FILE: &str = "estractor.lock"; // next to the output files
+pub const LOCK_MODE: &str = "wait"; // wait, skip or fail
+pub const LOCK_TTL: u64 = 120; // seconds, renewed every third while the run is alive
+pub const LOCK_WAIT: u64 = 300; // seconds to wait before failing
+pub const SLOTS_DIR: &str = ".slots"; // obsdatetime of each slot, next to the output files
+pub const SLOT_FILE_TTL: u64 = 7 * 24 * 3600; // seconds, slots are retried within hours, older ones are removed
+pub const SHARDS_DIR: &str = "shards"; // per task outputs before the merge, next to the output files
+pub const MERGE_MARKER: &str = ".merging"; // in a shard directory while a task merges it
+pub const MERGE_MARKER_TTL: u64 = 600; // seconds, older markers were left by a crashed merge
+pub const EMPTY_SHARD_MARKER: &str = "-- EMPTY"; // whole content of a shard no watchlist ISIN of the source went to
+pub const HTTP_CACHE_FILE: &str = "http-cache.json"; // validators per url, next to the output files
+pub const DAEMON_STATUS_FILE: &str = "daemon-status.json"; // next to the output files
+pub const ROUTES_FILE: &str = "routes.txt"; // next to the output files, where they persist
+pub const REDACTED: &str = "redacted"; // in place of secrets in the manifest settings
+pub const UNROUTED: &str = "unrouted"; // reported as a source, watchlist ISINs no source quotes
+// ISIN prefix -> site, used to route a mixed watchlist
+pub const ISIN_ROUTES: [(&str, &str); 3] = [
+    ("DE000V", "vontobel"),
+    ("NLBNP", "bnp"),
+    ("IT0006", "marex"),
+];
@@ -1,3 +1,10 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::definitions::globals::UNROUTED;
+use crate::writers::Columns;
+
 // type QuotesSharedState = Arc<Mutex<Vec<HashMap<String, String>>>>;
 
 // types
@@ -10,18 +17,178 @@ pub struct Source {
     pub extractor: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Quote {
     pub isin: String,
     pub name: String,
     pub ask: String,
     pub bid: String,
     pub currency: String,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>, // in-session or off-session, when tagged
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub not_modified: bool, // reused from the http cache on a 304
+}
+
+// where and when a quote was fetched, written as extra columns when enabled
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Provenance {
+    pub fetched_at: String, // RFC 3339, UTC
+    pub url: String,
+    pub http_status: u16,
+    pub extractor: String,
+    pub strategy: String, // selector or pattern that matched
+    pub run_id: String,
 }
 
 #[derive(Debug, Clone)]
-pub struct ISIN {
+pub struct Isin {
     pub isin: String,
     pub name: String,
     // pub url: Option<String>,
-}
\ No newline at end of file
+}
+
+// result of running one extractor over a page
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    pub matched: String, // matched element (selector) or matched text (pattern)
+    pub raw: String,
+    pub value: String,
+    pub strategy: String, // rule that produced the match
+    pub currency: Option<String>, // when the page states it, e.g. schema.org priceCurrency
+    pub bid: Option<String>,      // when the extractor finds one, e.g. a script
+}
+
+// rule of a source from the config, unset fields keep the builtin rule of its site
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionRule {
+    pub rule: Option<String>,      // selector, xpath, regex, or microdata / JSON-LD property
+    pub attribute: Option<String>, // selector and xpath: read this attribute instead of the text
+    pub script: Option<Script>,    // script extractor
+}
+
+// script of the script extractor, read from the file the config names
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub path: String,
+    pub code: String,
+    pub max_operations: u64,
+}
+
+// per source outcome of a run
+#[derive(Debug, Clone)]
+pub struct SourceReport {
+    pub site: String,
+    pub isins: usize,
+    pub quotes: usize,
+    pub blocked: usize, // answered with a challenge or consent page
+    pub not_modified: usize, // quotes reused from the http cache
+    pub cancelled: usize,
+    pub skipped: Vec<SkippedIsin>,
+    pub changed: Option<usize>, // quotes that moved since the latest observation, when compared
+    pub elapsed: Duration,
+    pub output: Option<OutputFile>,
+    pub error: Option<String>, // why the source did not run, such as an unreadable ISIN file
+}
+
+impl SourceReport {
+    pub fn empty(site: &str) -> SourceReport {
+        SourceReport {
+            site: site.to_string(),
+            isins: 0,
+            quotes: 0,
+            blocked: 0,
+            not_modified: 0,
+            cancelled: 0,
+            skipped: Vec::new(),
+            changed: None,
+            elapsed: Duration::ZERO,
+            output: None,
+            error: None,
+        }
+    }
+
+    // a source that could not run at all
+    pub fn failed(site: &str, error: String) -> SourceReport {
+        SourceReport {
+            error: Some(error),
+            ..SourceReport::empty(site)
+        }
+    }
+
+    // watchlist ISINs no source was found for, skipped as a source of their own
+    pub fn unrouted(isins: &[Isin]) -> SourceReport {
+        let skipped = isins.iter().map(|isin| SkippedIsin {
+            site: UNROUTED.to_string(),
+            isin: isin.isin.clone(),
+            reason: "no source found".to_string(),
+        });
+        SourceReport {
+            isins: isins.len(),
+            skipped: skipped.collect(),
+            ..SourceReport::empty(UNROUTED)
+        }
+    }
+}
+
+// part of the ISINs handled by one of `count` parallel tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+// which schedule slot and shard a run fills, both unset for plain runs
+#[derive(Debug, Clone, Default)]
+pub struct RunSlot {
+    pub slot: Option<String>,
+    pub shard: Option<Shard>,
+}
+
+// ISIN not requested because the source circuit breaker was open
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedIsin {
+    pub site: String,
+    pub isin: String,
+    pub reason: String,
+}
+
+// ISINs of one source that were not quoted for other reasons than a failed request
+#[derive(Debug, Clone, Default)]
+pub struct Extracted {
+    pub blocked: usize,
+    pub not_modified: usize,
+    pub cancelled: usize,
+    pub skipped: Vec<SkippedIsin>,
+}
+
+// settings shared by the source jobs of one run
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub output_path_prefix: String,
+    pub obsdatetime: String,
+    pub columns: Columns,
+    pub unchanged: String,
+    pub shard: Option<Shard>,
+}
+
+// output file as listed in the run manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputFile {
+    pub site: String,
+    pub file: String,
+    pub rows: usize,
+    pub sha256: String,
+    pub marker: String,
+}
+
+// input and output paths, after the environment overrides
+#[derive(Debug, Clone, Serialize)]
+pub struct Paths {
+    pub isin_path_prefix: String,
+    pub source_path: String,
+    pub output_path_prefix: String,
+    pub config_path: String,
+}
@@ -0,0 +1,249 @@
+use regex::{Captures, Regex};
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+
+use crate::definitions::config::Config;
+use crate::definitions::types::{Extraction, ExtractionRule, Source};
+#[cfg(feature = "js")]
+use crate::javascript;
+use crate::scripting;
+use crate::utils::price_formatter;
+use crate::xpath::XPath;
+
+// extractor types as written in the sources file
+pub const EXTRACTORS: [&str; 7] = ["selector", "pattern", "xpath", "microdata", "jsonld", "script", "js"];
+// schema.org property read by the microdata and JSON-LD extractors, unless the rule names another
+const OFFER_PRICE: &str = "price";
+
+pub fn get_ask_price_selector(site: &str) -> Result<&'static str, &'static str> {
+    match site.trim() {
+        "" => Err("invalid site"),
+        "marex" => Ok("#product-ask-price"),
+        "bnp" => Ok(r#"span[data-field="ask"]"#),
+        // "vontobel" => Ok(r#"h2[data-testid="buy_price_label"]"#),
+        _ => Err("site not found"),
+    }
+}
+
+pub fn get_ask_price_pattern(site: &str) -> Result<&'static str, &'static str> {
+    let vp = r#"\"ask\":[0-9]+\.?[0-9]*,"#;
+    match site.trim() {
+        "" => Err("invalid site"),
+        "vontobel" => Ok(vp), // "ask":[0-9]+\.?[0-9]*,
+        _ => Err("site not found"),
+    }
+}
+
+fn extraction(matched: String, raw: &str, strategy: String) -> Extraction {
+    Extraction {
+        matched,
+        value: price_formatter(raw),
+        raw: raw.trim().to_string(),
+        strategy,
+        currency: None,
+        bid: None,
+    }
+}
+
+// "rule @attribute" in the provenance
+pub fn strategy(rule: &str, attribute: Option<&str>) -> String {
+    match attribute {
+        Some(attribute) => format!("{} @{}", rule, attribute),
+        None => rule.to_string(),
+    }
+}
+
+fn get_price_by_selector(html_content: &str, source_site: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let document = Html::parse_document(html_content);
+    let selector_rule = match &rule.rule {
+        Some(selector_rule) => selector_rule.as_str(),
+        None => get_ask_price_selector(source_site)?,
+    };
+    let product_ask_price_sel = Selector::parse(selector_rule).map_err(|_e| "invalid selector")?;
+    let ask_price = document
+        .select(&product_ask_price_sel)
+        .next()
+        .ok_or("no element matched")?;
+    let text = match &rule.attribute {
+        Some(attribute) => ask_price.value().attr(attribute).ok_or("attribute not found")?.to_string(),
+        None => ask_price.text().collect::<Vec<_>>().join(""),
+    };
+    Ok(extraction(ask_price.html(), &text, strategy(selector_rule, rule.attribute.as_deref())))
+}
+
+// "ask":101.5, -> 101.5, or the first capture group of the rule when it has one
+pub fn pattern_extraction(captures: &Captures, rule: &str) -> Extraction {
+    let mat = captures.get(0).map(|m| m.as_str()).unwrap_or_default();
+    let raw = match captures.get(1) {
+        Some(group) => group.as_str(),
+        None => mat.split(":").nth(1).and_then(|from| from.split(",").next()).unwrap_or(mat),
+    };
+    extraction(mat.to_string(), raw, rule.to_string())
+}
+
+fn get_price_by_pattern(html_content: &str, source_site: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let pattern_rule = match &rule.rule {
+        Some(pattern_rule) => pattern_rule.as_str(),
+        None => get_ask_price_pattern(source_site)?,
+    };
+    let re = Regex::new(pattern_rule).map_err(|_e| "invalid pattern")?;
+    let captures = re.captures(html_content).ok_or("no pattern matched")?;
+    Ok(pattern_extraction(&captures, pattern_rule))
+}
+
+// no builtin xpath rules, the config gives one per source
+fn get_price_by_xpath(html_content: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let xpath_rule = rule.rule.as_deref().ok_or("no xpath rule for the site")?;
+    let mut xpath_rule = xpath_rule.to_string();
+    if let Some(attribute) = &rule.attribute {
+        xpath_rule = format!("{}/@{}", xpath_rule, attribute);
+    }
+    let xpath = XPath::parse(&xpath_rule).map_err(|_e| "invalid xpath")?;
+    let document = Html::parse_document(html_content);
+    let (matched, text) = xpath.first(&document).ok_or("no node matched")?;
+    Ok(extraction(matched, &text, xpath_rule))
+}
+
+// content attribute for meta and the like, text otherwise
+fn itemprop_value(element: &ElementRef) -> String {
+    match element.value().attr("content") {
+        Some(content) => content.to_string(),
+        None => element.text().collect::<String>(),
+    }
+}
+
+// first itemprop="price" in an Offer item, or anywhere on the page when there is no Offer
+fn get_price_by_microdata(html_content: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let document = Html::parse_document(html_content);
+    let property = rule.rule.as_deref().unwrap_or(OFFER_PRICE);
+    let offer_sel = Selector::parse(r#"[itemtype*="schema.org/Offer"], [itemtype*="schema.org/AggregateOffer"]"#).unwrap();
+    let price_sel = Selector::parse(&format!(r#"[itemprop="{}"]"#, property)).map_err(|_e| "invalid property")?;
+    let currency_sel = Selector::parse(r#"[itemprop="priceCurrency"]"#).unwrap();
+    let scope = document.select(&offer_sel).find(|offer| offer.select(&price_sel).next().is_some());
+    let (price, currency) = match scope {
+        Some(offer) => (offer.select(&price_sel).next(), offer.select(&currency_sel).next()),
+        None => (document.select(&price_sel).next(), document.select(&currency_sel).next()),
+    };
+    let price = price.ok_or("no itemprop matched")?;
+    let mut found = extraction(price.html(), &itemprop_value(&price), format!("itemprop={}", property));
+    found.currency = currency.map(|currency| itemprop_value(&currency).trim().to_string());
+    Ok(found)
+}
+
+fn is_offer(object: &serde_json::Map<String, Value>) -> bool {
+    let offer = |t: &Value| matches!(t.as_str(), Some("Offer" | "AggregateOffer"));
+    match object.get("@type") {
+        Some(Value::Array(types)) => types.iter().any(offer),
+        Some(t) => offer(t),
+        None => false,
+    }
+}
+
+// first Offer with the property, depth first through @graph, offers and the rest
+fn find_offer<'a>(value: &'a Value, property: &str) -> Option<&'a serde_json::Map<String, Value>> {
+    match value {
+        Value::Object(object) if is_offer(object) && object.contains_key(property) => Some(object),
+        Value::Object(object) => object.values().find_map(|value| find_offer(value, property)),
+        Value::Array(values) => values.iter().find_map(|value| find_offer(value, property)),
+        _ => None,
+    }
+}
+
+fn get_price_by_jsonld(html_content: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let document = Html::parse_document(html_content);
+    let property = rule.rule.as_deref().unwrap_or(OFFER_PRICE);
+    let script_sel = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    for script in document.select(&script_sel) {
+        // one broken block does not hide the others
+        let Ok(json) = serde_json::from_str::<Value>(&script.text().collect::<String>()) else {
+            continue;
+        };
+        let Some(offer) = find_offer(&json, property) else {
+            continue;
+        };
+        let raw = match &offer[property] {
+            Value::String(price) => price.clone(),
+            Value::Number(price) => price.to_string(),
+            // a price object or null, another block may have a usable one
+            _ => continue,
+        };
+        let matched = serde_json::to_string(offer).unwrap_or_default();
+        let mut found = extraction(matched, &raw, format!("Offer.{}", property));
+        found.currency = offer.get("priceCurrency").and_then(|c| c.as_str()).map(|c| c.to_string());
+        return Ok(found);
+    }
+    Err("no offer found")
+}
+
+fn get_price_by_script(html_content: &str, source_site: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let script = rule.script.as_ref().ok_or("no script for the site")?;
+    scripting::run(script, html_content, source_site)
+}
+
+// the rule is a JavaScript expression over the globals the inline scripts of the page set
+#[cfg(feature = "js")]
+fn get_price_by_js(html_content: &str, source_site: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    let expression = rule.rule.as_deref().ok_or("no js expression for the site")?;
+    javascript::run(expression, html_content, source_site)
+}
+
+#[cfg(not(feature = "js"))]
+fn get_price_by_js(_html_content: &str, _source_site: &str, _rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    Err("estractor built without the js feature")
+}
+
+pub fn extract(extractor: &str, html_content: &str, source_site: &str, rule: &ExtractionRule) -> Result<Extraction, &'static str> {
+    match extractor.trim() {
+        "selector" => get_price_by_selector(html_content, source_site, rule),
+        "pattern" => get_price_by_pattern(html_content, source_site, rule),
+        "xpath" => get_price_by_xpath(html_content, rule),
+        "microdata" => get_price_by_microdata(html_content, rule),
+        "jsonld" => get_price_by_jsonld(html_content, rule),
+        "script" => get_price_by_script(html_content, source_site, rule),
+        "js" => get_price_by_js(html_content, source_site, rule),
+        _ => Err("Price not found"),
+    }
+}
+
+// rules from the config are checked before any request, for the extractor each source uses
+pub fn check_rules(sources: &[Source], config: &Config) -> Result<(), String> {
+    for source in sources {
+        let rule = config.extraction_rule(&source.site);
+        let invalid = |e: String| format!("sources.{}.rule: {}", source.site, e);
+        let not_used = |field: &str| format!("sources.{}.{}: not used by the {} extractor", source.site, field, source.extractor);
+        if rule.script.is_some() && source.extractor.trim() != "script" {
+            return Err(not_used("script"));
+        }
+        if rule.attribute.is_some() && !matches!(source.extractor.trim(), "selector" | "xpath") {
+            return Err(not_used("attribute"));
+        }
+        match (source.extractor.trim(), &rule.rule) {
+            ("selector", Some(selector_rule)) => {
+                Selector::parse(selector_rule).map_err(|e| invalid(e.to_string()))?;
+            }
+            ("pattern", Some(pattern_rule)) => {
+                Regex::new(pattern_rule).map_err(|e| invalid(e.to_string()))?;
+            }
+            ("xpath", Some(xpath_rule)) => {
+                XPath::parse(xpath_rule).map_err(invalid)?;
+            }
+            ("xpath", None) => return Err(invalid("the xpath extractor needs a rule".to_string())),
+            ("script", Some(_rule)) => return Err(not_used("rule")),
+            ("script", None) => {
+                let script = rule.script.as_ref().ok_or(format!("sources.{}.script: the script extractor needs a readable script", source.site))?;
+                scripting::compile(script).map_err(|e| format!("sources.{}.script: {}", source.site, e))?;
+            }
+            ("js", None) => return Err(invalid("the js extractor needs an expression".to_string())),
+            #[cfg(feature = "js")]
+            ("js", Some(expression)) => {
+                javascript::check(expression).map_err(invalid)?;
+            }
+            #[cfg(not(feature = "js"))]
+            ("js", Some(_expression)) => {
+                return Err(format!("sources.{}: the js extractor needs estractor built with --features js", source.site));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+
+use reqwest::header::{ETAG, HeaderName, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, Response, StatusCode};
+
+use crate::blocking::BlockDetector;
+use crate::cache::{CacheEntry, HttpCache, rule_key};
+use crate::definitions::globals::DEF_PRICE;
+use crate::definitions::types::{ExtractionRule, Isin, Provenance, Quote, Source};
+use crate::extractors::extract;
+use crate::http::read_body_until;
+use crate::streaming::StreamScan;
+use crate::utils::log;
+
+pub async fn fetch(client: &Client, url: &str) -> Result<Response, reqwest::Error> {
+    log!("Request to {}:...", url);
+    client.get(url).send().await
+}
+
+#[derive(Debug, Clone)]
+pub enum FetchError {
+    Failed(String),
+    Blocked(String), // challenge or consent page instead of the product page
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::Failed(e) => write!(f, "{}", e),
+            FetchError::Blocked(e) => write!(f, "blocked: {}", e),
+        }
+    }
+}
+
+// conditional when the page was cached with validators
+async fn fetch_conditional(client: &Client, url: &str, cached: Option<&CacheEntry>) -> Result<Response, reqwest::Error> {
+    log!("Request to {}:...", url);
+    let mut request = client.get(url);
+    if let Some(etag) = cached.and_then(|entry| entry.etag.as_ref()) {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = cached.and_then(|entry| entry.last_modified.as_ref()) {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    request.send().await
+}
+
+fn header_value(response: &Response, name: HeaderName) -> Option<String> {
+    response.headers().get(name)?.to_str().ok().map(|value| value.to_string())
+}
+
+// how the page of a source is fetched and read
+#[derive(Clone, Copy)]
+pub struct FetchOptions<'a> {
+    pub detector: &'a BlockDetector,
+    pub rule: &'a ExtractionRule,
+    pub max_response_bytes: usize,
+    pub cache: Option<&'a HttpCache>, // conditional requests
+    pub streaming: bool,              // extract while downloading, stop at the match
+}
+
+// fetch the product page of one ISIN and extract its quote,
+// a 304 answer to a conditional request reuses the cached values
+pub async fn fetch_quote(client: &Client, source: &Source, isin: &Isin, options: &FetchOptions<'_>) -> Result<Quote, FetchError> {
+    let FetchOptions { detector, rule, max_response_bytes, cache, streaming } = *options;
+    let failed = |e: String| FetchError::Failed(e);
+    let url = [source.base_url.as_str(), isin.isin.as_str()].concat();
+    let rule_key = rule_key(&source.extractor, rule);
+    let cached = cache.and_then(|cache| cache.get(&source.site, &url, &rule_key));
+    let response = fetch_conditional(client, &url, cached.as_ref()).await.map_err(|e| match e.is_timeout() {
+        true => failed(format!("Timed out: {}", e)),
+        false => failed(format!("Error occurred: {}", e)),
+    })?;
+    let fetched_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let status = response.status();
+    let http_status = status.as_u16();
+    let provenance = |strategy: String| Provenance {
+        fetched_at: fetched_at.clone(),
+        url: url.clone(),
+        http_status,
+        extractor: source.extractor.clone(),
+        strategy,
+        run_id: String::new(), // set by the scheduler
+    };
+    if status == StatusCode::NOT_MODIFIED {
+        let entry = cached.ok_or_else(|| failed(format!("Price {}: not modified, but not cached", isin.isin)))?;
+        log!("[CACHE] Price {}: not modified since {}, {}", isin.isin, entry.fetched_at, entry.ask);
+        return Ok(Quote {
+            isin: isin.isin.clone(),
+            name: isin.name.clone(),
+            ask: entry.ask,
+            bid: entry.bid,
+            currency: entry.currency,
+            provenance: Some(provenance(entry.strategy)),
+            session: None,
+            not_modified: true,
+        });
+    }
+    let etag = header_value(&response, ETAG);
+    let last_modified = header_value(&response, LAST_MODIFIED);
+    let mut scan = match streaming && status.is_success() {
+        true => StreamScan::new(&source.extractor, &source.site, rule),
+        false => None,
+    };
+    let html_content = read_body_until(response, max_response_bytes, |text| scan.as_mut().is_some_and(|scan| scan.feed(text)))
+        .await
+        .map_err(|e| failed(format!("Error occurred: {}", e)))?;
+    // walls come as 200 as well as 403 or 503 and may carry something that looks like a price,
+    // so the body (as far as it was read) is checked first
+    if let Some(signature) = detector.detect(&html_content, &source.site) {
+        return Err(FetchError::Blocked(format!("{} for {} (status {})", signature, isin.isin, http_status)));
+    }
+    // without a streamed match the whole page goes through the DOM extractor,
+    // so that both paths fail alike
+    let extraction = match scan.and_then(|scan| scan.finish().ok()) {
+        Some(extraction) => extraction,
+        None => {
+            if !status.is_success() {
+                return Err(failed(format!("Received a non-success status: {}", status)));
+            }
+            // parsing the page and running its scripts (js and script extractors) is blocking work,
+            // kept off the threads that drive the downloads
+            let (extractor, site, rule) = (source.extractor.clone(), source.site.clone(), rule.clone());
+            tokio::task::spawn_blocking(move || extract(&extractor, &html_content, &site, &rule))
+                .await
+                .map_err(|e| failed(format!("Price {}: {}", isin.isin, e)))?
+                .map_err(|e| failed(format!("Price {}: {}", isin.isin, e)))?
+        }
+    };
+    log!("Price {}: {}", isin.isin, extraction.value);
+    let quote = Quote {
+        isin: isin.isin.clone(),
+        name: isin.name.clone(),
+        ask: extraction.value,
+        bid: extraction.bid.unwrap_or(DEF_PRICE.to_string()),
+        currency: extraction.currency.unwrap_or("EUR".to_string()),
+        provenance: Some(provenance(extraction.strategy.clone())),
+        session: None,
+        not_modified: false,
+    };
+    if let Some(cache) = cache {
+        match (etag, last_modified) {
+            (None, None) => cache.remove(&source.site, &url),
+            (etag, last_modified) => cache.insert(
+                &source.site,
+                &url,
+                CacheEntry {
+                    etag,
+                    last_modified,
+                    ask: quote.ask.clone(),
+                    bid: quote.bid.clone(),
+                    currency: quote.currency.clone(),
+                    strategy: extraction.strategy,
+                    fetched_at: fetched_at.clone(),
+                    rule: rule_key,
+                },
+            ),
+        }
+    }
+    Ok(quote)
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, ClientBuilder, Proxy, Response};
+
+use crate::definitions::config::HttpConfig;
+use crate::definitions::globals::USER_AGENT;
+
+// every client starts from here, a User-Agent in the session headers replaces this one
+pub fn client_builder(http: &HttpConfig) -> Result<ClientBuilder, String> {
+    // TODO: make user-agent random
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(http.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(http.read_timeout_secs))
+        .timeout(Duration::from_secs(http.timeout_secs))
+        .gzip(http.gzip)
+        .brotli(http.brotli);
+    if !http.http2 {
+        builder = builder.http1_only();
+    }
+    if let Some(proxy) = &http.proxy {
+        builder = builder.proxy(Proxy::all(proxy).map_err(|e| format!("proxy {}: {}", proxy, e))?);
+    }
+    if let Some(ca_bundle) = &http.ca_bundle {
+        let pem = fs::read(ca_bundle).map_err(|e| format!("{}: {}", ca_bundle, e))?;
+        for certificate in Certificate::from_pem_bundle(&pem).map_err(|e| format!("{}: {}", ca_bundle, e))? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+// body as text, given up past `max_bytes` (0 is no limit) after decompression
+pub async fn read_body(response: Response, max_bytes: usize) -> Result<String, String> {
+    read_body_until(response, max_bytes, |_text| false).await
+}
+
+// same, handing the text to `scan` as it arrives; the download stops once `scan` is true
+// and the body read so far is returned
+pub async fn read_body_until(
+    mut response: Response,
+    max_bytes: usize,
+    mut scan: impl FnMut(&str) -> bool,
+) -> Result<String, String> {
+    // counted as read rather than from Content-Length, a scan may stop well before the end
+    let too_large = || format!("response larger than {} bytes", max_bytes);
+    let mut body = Vec::new();
+    let mut scanned = Some(0); // None once the body turns out not to be UTF-8
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        body.extend_from_slice(&chunk);
+        if max_bytes > 0 && body.len() > max_bytes {
+            return Err(too_large());
+        }
+        let Some(from) = scanned else {
+            continue;
+        };
+        // a character cut by the chunk boundary waits for the next chunk
+        let text = match str::from_utf8(&body[from..]) {
+            Ok(text) => text,
+            Err(e) if e.error_len().is_none() => str::from_utf8(&body[from..from + e.valid_up_to()]).unwrap_or_default(),
+            Err(_e) => {
+                scanned = None;
+                continue;
+            }
+        };
+        let stop = scan(text);
+        scanned = Some(from + text.len());
+        if stop {
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
@@ -0,0 +1,87 @@
+use boa_engine::property::Attribute;
+use boa_engine::{Context, Script, Source, js_string};
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+use crate::definitions::types::Extraction;
+use crate::utils::price_formatter;
+
+// a page script stuck in a loop or a recursion fails instead of holding the worker
+const LOOP_ITERATION_LIMIT: u64 = 100_000;
+const RECURSION_LIMIT: usize = 256;
+
+// inline classic scripts, not external (src), JSON or module ones
+fn inline_scripts(html_content: &str) -> Vec<String> {
+    let document = Html::parse_document(html_content);
+    let script_sel = Selector::parse("script:not([src])").unwrap();
+    document
+        .select(&script_sel)
+        .filter(|script| {
+            let kind = script.value().attr("type").map(|kind| kind.trim().to_ascii_lowercase());
+            matches!(kind.as_deref(), None | Some("" | "text/javascript" | "application/javascript"))
+        })
+        .map(|script| script.text().collect())
+        .collect()
+}
+
+// plain ECMAScript: no DOM, network or timers; window and self are the global object,
+// so that `window.__INITIAL_STATE__ = ...` assignments land in globals
+fn context() -> Context {
+    let mut context = Context::default();
+    context.runtime_limits_mut().set_loop_iteration_limit(LOOP_ITERATION_LIMIT);
+    context.runtime_limits_mut().set_recursion_limit(RECURSION_LIMIT);
+    let global = context.global_object();
+    for name in [js_string!("window"), js_string!("self")] {
+        // defining a property on a fresh global object cannot fail
+        let _ = context.register_global_property(name, global.clone(), Attribute::all());
+    }
+    context
+}
+
+pub fn check(expression: &str) -> Result<(), String> {
+    let mut context = context();
+    Script::parse(Source::from_bytes(expression), None, &mut context)
+        .map(|_script| ())
+        .map_err(|e| e.to_string())
+}
+
+fn field(value: Option<&Value>) -> Option<String> {
+    match value {
+        Some(Value::String(text)) => Some(text.clone()),
+        Some(Value::Number(number)) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+// runs the inline scripts of the page, then the rule expression over the globals they left;
+// it gives the ask price, or an object with ask and optionally bid and currency
+pub fn run(expression: &str, html_content: &str, source_site: &str) -> Result<Extraction, &'static str> {
+    let scripts = inline_scripts(html_content);
+    let mut context = context();
+    // page scripts that use the DOM throw, the ones before them already ran
+    let failed = scripts
+        .iter()
+        .filter(|script| context.eval(Source::from_bytes(script.as_str())).is_err())
+        .count();
+    let value = context.eval(Source::from_bytes(expression)).map_err(|e| {
+        eprintln!("[JS] {}: {}: {} ({} of {} inline scripts failed)", source_site, expression, e, failed, scripts.len());
+        "expression failed"
+    })?;
+    if value.is_null_or_undefined() {
+        return Err("expression gave no price");
+    }
+    let json = value.to_json(&mut context).map_err(|_e| "expression gave no price")?;
+    let ask = match &json {
+        Value::Object(object) => field(object.get("ask")),
+        json => field(Some(json)),
+    };
+    let ask = ask.ok_or("expression gave no price")?;
+    Ok(Extraction {
+        matched: json.to_string(),
+        value: price_formatter(&ask),
+        raw: ask.trim().to_string(),
+        strategy: expression.to_string(),
+        currency: field(json.get("currency")).map(|currency| currency.trim().to_string()),
+        bid: field(json.get("bid")).map(|bid| price_formatter(&bid)),
+    })
+}
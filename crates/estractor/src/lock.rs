@@ -0,0 +1,192 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::utils::log;
+
+// content of the lock file, the lease is renewed while the run is alive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub holder: String, // host:pid
+    pub run_id: String,
+    pub acquired_at: String,
+    pub expires_at: String,
+}
+
+impl Lease {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        match DateTime::parse_from_rfc3339(&self.expires_at) {
+            Ok(expires_at) => expires_at < now,
+            Err(_e) => true,
+        }
+    }
+}
+
+fn holder() -> String {
+    let host = env::var("HOSTNAME").unwrap_or("localhost".to_string());
+    format!("{}:{}", host, std::process::id())
+}
+
+fn read_lease(path: &str) -> Option<Lease> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+// create_new maps to an if-generation-match=0 precondition on gcsfuse,
+// so only one process can create the file, renames are not relied upon
+fn create_lease(path: &str, lease: &Lease) -> Result<bool, String> {
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(mut file) => {
+            let content = serde_json::to_string_pretty(lease).map_err(|e| e.to_string())?;
+            file.write_all(content.as_bytes()).map_err(|e| format!("{}: {}", path, e))?;
+            file.sync_all().map_err(|e| format!("{}: {}", path, e))?;
+            Ok(true)
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(format!("{}: {}", path, e)),
+    }
+}
+
+// content and modification time, tell one lease file from the next
+type Snapshot = (Vec<u8>, SystemTime);
+
+fn snapshot(path: &str) -> Option<Snapshot> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    Some((fs::read(path).ok()?, modified))
+}
+
+// removes the lease file only if it is still the one read: it is first moved to a name of
+// our own, so two runs taking over the same expired lease cannot delete the fresh lease the
+// faster one created; a lease that changed meanwhile is put back
+fn remove_lease(path: &str, holder: &str, read: &Snapshot) -> Result<(), String> {
+    let aside = format!("{}.{}.stale", path, holder.replace(':', "-"));
+    match fs::rename(path, &aside) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()), // taken over by another run
+        Err(e) => return Err(format!("{}: {}", path, e)),
+    }
+    if let Some(moved) = snapshot(&aside)
+        && moved != *read
+    {
+        log!("[LOCK] {} was renewed or taken over meanwhile, putting it back", path);
+        if let Ok(mut file) = OpenOptions::new().write(true).create_new(true).open(path) {
+            file.write_all(&moved.0).map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    fs::remove_file(&aside).map_err(|e| format!("{}: {}", aside, e))
+}
+
+// held lock, the lease is renewed every third of the ttl and removed on drop
+pub struct RunLock {
+    path: String,
+    holder: String,
+    renew: CancellationToken,
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        self.renew.cancel();
+        // a lease that expired and was taken over is not ours to remove
+        if read_lease(&self.path).is_some_and(|lease| lease.holder == self.holder) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn renew_lease(path: String, mut lease: Lease, ttl: Duration, stop: CancellationToken) {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(ttl / 3) => {}
+                _ = stop.cancelled() => return,
+            }
+            if read_lease(&path).is_none_or(|current| current.holder != lease.holder) {
+                eprintln!("[LOCK] lease on {} was lost", path);
+                return;
+            }
+            lease.expires_at = (Utc::now() + ttl).to_rfc3339();
+            if let Err(e) = fs::write(&path, serde_json::to_string_pretty(&lease).unwrap_or_default()) {
+                eprintln!("[LOCK] cannot renew {}: {}", path, e);
+            }
+        }
+    });
+}
+
+// lock on the output directory for one run, mode says what to do when another run holds it:
+// wait (up to `wait`, then fail), skip (Ok(None)) or fail; a cancelled wait fails
+pub async fn acquire(
+    output_path_prefix: &str,
+    lock_file: &str,
+    run_id: &str,
+    mode: &str,
+    ttl: Duration,
+    wait: Duration,
+    cancel: &CancellationToken,
+) -> Result<Option<RunLock>, String> {
+    let path = [output_path_prefix, lock_file].concat();
+    let _ = fs::create_dir_all(output_path_prefix);
+    let holder = holder();
+    let waited = tokio::time::Instant::now();
+    loop {
+        let now = Utc::now();
+        let lease = Lease {
+            holder: holder.clone(),
+            run_id: run_id.to_string(),
+            acquired_at: now.to_rfc3339(),
+            expires_at: (now + ttl).to_rfc3339(),
+        };
+        if create_lease(&path, &lease)? {
+            log!("[LOCK] acquired {} until {}", path, lease.expires_at);
+            let renew = CancellationToken::new();
+            renew_lease(path.clone(), lease, ttl, renew.clone());
+            return Ok(Some(RunLock { path, holder, renew }));
+        }
+        let Some(read) = snapshot(&path) else {
+            // released meanwhile, or unreadable
+            pause(&path, Duration::from_secs(1), cancel).await?;
+            continue;
+        };
+        let current = match serde_json::from_slice::<Lease>(&read.0) {
+            Ok(current) => current,
+            Err(_e) => {
+                // being written by its holder, or left empty by a crash
+                if read.1.elapsed().is_ok_and(|age| age > ttl) {
+                    log!("[LOCK] removing unreadable lock {}", path);
+                    remove_lease(&path, &holder, &read)?;
+                    continue;
+                }
+                pause(&path, Duration::from_secs(1), cancel).await?;
+                continue;
+            }
+        };
+        if current.expired(now) {
+            log!("[LOCK] lease of {} ({}) expired at {}, taking over", current.holder, current.run_id, current.expires_at);
+            remove_lease(&path, &holder, &read)?;
+            continue;
+        }
+        let held = format!("{} is locked by {} ({}) until {}", path, current.holder, current.run_id, current.expires_at);
+        match mode {
+            "skip" => {
+                log!("[LOCK] {}, skipping this run", held);
+                return Ok(None);
+            }
+            "wait" if waited.elapsed() < wait => {
+                log!("[LOCK] {}, waiting", held);
+                pause(&path, (ttl / 3).min(wait.saturating_sub(waited.elapsed())).max(Duration::from_secs(1)), cancel).await?;
+            }
+            _ => return Err(held),
+        }
+    }
+}
+
+// SIGTERM or the deadline ends the wait
+async fn pause(path: &str, duration: Duration, cancel: &CancellationToken) -> Result<(), String> {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => Ok(()),
+        _ = cancel.cancelled() => Err(format!("cancelled while waiting for {}", path)),
+    }
+}
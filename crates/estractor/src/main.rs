@@ -1,248 +1,86 @@
+mod bench;
+mod blocking;
+mod breaker;
+mod cache;
+mod calendar;
+mod daemon;
+mod dedup;
 mod definitions;
+mod extractors;
+mod fetcher;
+mod http;
+#[cfg(feature = "js")]
+mod javascript;
+mod lock;
+mod manifest;
+mod migrate;
+mod outcome;
+mod probe;
+mod quote;
+mod readers;
+mod routing;
+mod runner;
+mod scheduler;
+mod scripting;
+mod session;
+mod shards;
+mod signals;
+mod streaming;
+mod slots;
 mod utils;
+mod writers;
+mod xpath;
 
+use calendar::Calendar;
 use clap::Parser;
+use common::naming::NamingScheme;
 use definitions::globals::*;
 use definitions::types::*;
-use definitions::args::Args;
-use utils::price_formatter;
+use definitions::args::{Args, Command};
+use outcome::Outcome;
+use readers::{read_config_from_file, read_sources_from_file};
+use utils::{log, reserve_stdout};
 
-use chrono;
 // use csv::Writer;
-use reqwest::Client;
-use scraper::{Html, Selector};
-use std::fs::{self, File};
-use std::io::{BufReader, prelude::*};
-use std::path::Path;
 // use std::result;
-use regex::Regex;
-use std::sync::{Arc, Mutex};
-use std::{env, error::Error, time::Duration};
-use tokio;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, error::Error, process};
+use tokio_util::sync::CancellationToken;
 
 //use crate::definitions::globals::OUTPUT_PATH_PREFIX; // Async runtime
 
-fn get_ask_price_selector(site: &str) -> Result<Selector, &'static str> {
-    match site.trim() {
-        "" => Err("invalid site"),
-        "marex" => Ok(Selector::parse("#product-ask-price").unwrap()),
-        "bnp" => Ok(Selector::parse(r#"span[data-field="ask"]"#).unwrap()),
-        // "vontobel" => Ok(Selector::parse(r#"h2[data-testid="buy_price_label"]"#).unwrap()),
-        _ => Err("site not found"),
-    }
-}
-
-fn get_ask_price_pattern(site: &str) -> Result<Regex, &'static str> {
-    let vp = r#"\"ask\":[0-9]+\.?[0-9]*,"#;
-    match site.trim() {
-        "" => Err("invalid site"),
-        "vontobel" => Ok(Regex::new(vp).unwrap()), // "ask":[0-9]+\.?[0-9]*,
-        _ => Err("site not found"),
-    }
-}
-
-fn read_sources_from_file(source_path: &str) -> Vec<Source> {
-    let path = Path::new(source_path);
-    let display = path.display();
-
-    // Open the path in read-only mode, returns `io::Result<File>`
-    let file = match File::open(&path) {
-        Err(why) => panic!("couldn't open {}: {}", display, why),
-        Ok(file) => file,
-    };
-    let mut start = false;
-    let reader = BufReader::new(file);
-    let mut sources: Vec<Source> = Vec::new();
-
-    for line_result in reader.lines() {
-        //let line = line_result?;
-        let line = line_result.unwrap();
-        let line = line.trim(); // Remove leading and trailing whitespace
-        if line.len() <= 0 {
-            continue;
-        }
-        if start {
-            if line.contains("-- END") {
-                start = false;
-            } else {
-                let cols = line.split(",");
-                let collection = cols.collect::<Vec<&str>>();
-                if collection.len() == 4 {
-                    println!("SOURCE: {:?}", collection);
-                    sources.push(Source {
-                        site: collection[0].trim().to_string(),
-                        content_type: collection[1].trim().to_string(),
-                        extractor: collection[2].trim().to_string(),
-                        base_url: collection[3].trim().to_string(),
-                    });
-                } else {
-                    println!("Source Error: {}", line);
-                }
-                // dbg!(collection);
-            }
-        } else {
-            start = line.contains("-- START");
-        }
-    }
-    sources
-}
-
-fn get_price_by_selector(html_content: &str, source_site: &str) -> Result<String, &'static str> {
-    let document = Html::parse_document(&html_content);
-    let product_ask_price_sel = get_ask_price_selector(&source_site)?;
-    let ask_price = document.select(&product_ask_price_sel).next().unwrap();
-    let text = ask_price
-        .text()
-        .collect::<Vec<_>>()
-        .join("")
-        .trim()
-        .to_string();
-    return Ok(price_formatter(&text));
+// settings that can never work, a retry would not help
+fn config_error(e: impl std::fmt::Display) -> ! {
+    eprintln!("Config Error: {}", e);
+    process::exit(EXIT_CONFIG);
 }
 
-fn get_price_by_pattern(html_content: &str, source_site: &str) -> Result<String, &'static str> {
-    //let document = Html::parse_document(&html_content);
-    let re = get_ask_price_pattern(&source_site)?;
-    let mat = re.find(html_content).unwrap().as_str();
-    let from: Vec<&str> = mat.split(":").collect();
-    let to: Vec<&str> = from[1].split(",").collect();
-    let price = price_formatter(&to[0]);
-    Ok(price)
-}
-
-fn read_isins_from_file(isin_path: &str) -> Result<Vec<ISIN>, std::io::Error> {
-    //let path = env::current_dir().unwrap();
-    let path = Path::new(isin_path);
-    // let display = path.display();
-
-    // Open the path in read-only mode, returns `io::Result<File>`
-    let file = File::open(&path)?; //{
-    //     Err(why) => panic!("couldn't open {}: {}", display, why),
-    //     Ok(file) => file,
-    // };
-    let mut start = false;
-    let reader = BufReader::new(file);
-    let mut isins: Vec<ISIN> = Vec::new();
-
-    for line_result in reader.lines() {
-        //let line = line_result?;
-        let line = line_result.unwrap();
-        let line = line.trim(); // Remove leading and trailing whitespace
-        if line.len() <= 0 {
-            continue;
-        }
-        if start {
-            if line.contains("-- END") {
-                start = false;
-            } else {
-                let line = line.split(",").collect::<Vec<&str>>();
-                if line.len() < 2 {
-                    println!("[ISIN] discarding line: {:?}", line);
-                    continue;
-                }
-                isins.push(ISIN {
-                    isin: line[0].trim().to_string(),
-                    name: line[1].trim().to_string(),
-                });
+// reports a partial or failed outcome and exits with its code, returns when all went well
+fn exit_with(outcome: &Outcome) {
+    match outcome {
+        Outcome::Ok => return,
+        Outcome::Partial(issues) => {
+            for issue in issues {
+                eprintln!("Partial: {}", issue);
             }
-        } else {
-            start = line.contains("-- START");
         }
+        Outcome::Failed(reason) => eprintln!("Failed: {}", reason),
     }
-    Ok(isins)
-}
-
-async fn extract_quotes_from_source(
-    source: &Source,
-    isins: &Vec<ISIN>,
-) -> Result<Vec<Quote>, std::io::Error> {
-    println!("\n--> init for Source: {:?}", source);
-
-    let results = Arc::new(Mutex::new(Vec::new()));
-
-    tokio::time::sleep(Duration::from_secs(2)).await;
-
-    // Async client
-    let client = Client::new();
-    // Vector to hold futures
-    let mut tasks = vec![];
-
-    for isin in isins {
-        let url = [source.base_url.clone(), isin.isin.clone()].concat();
-        println!("> ISIN: {} URL: {}", isin.isin, url);
-        let client = client.clone();
-        let r = Arc::clone(&results);
-        // Spawn async task for each request
-        let source = source.clone();
-        let isin = isin.clone();
-        let task = tokio::spawn(async move {
-            println!("Request to {}:...", url);
-            // TODO: make user-agent random
-            let response = client
-                        .get(url)
-                        .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/58.0.3029.110 Safari/537.36")
-                        .send().await; //?.text().await?;
-            match response {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        let html_content = response.text().await?;
-                        let price = match source.extractor.as_str() {
-                            "selector" => get_price_by_selector(&html_content, &source.site),
-                            "pattern" => get_price_by_pattern(&html_content, &source.site),
-                            _ => Err("Price not found"),
-                        };
-                        println!("Price {}: {}", isin.isin, price.clone().unwrap());
-                        let mut r = r.lock().unwrap();
-                        r.push(Quote {
-                            isin: isin.isin.clone(),
-                            name: isin.name.clone(),
-                            ask: price.unwrap(),
-                            bid: DEF_PRICE.to_string(),
-                            currency: "EUR".to_string(),
-                        });
-                    } else {
-                        println!("\nReceived a non-success status: {}", response.status());
-                    }
-                }
-                Err(e) => {
-                    // Log the error if the request fails
-                    eprintln!("\nError occurred: {}", e);
-                }
-            }
-            //println!("Response from {}: {}", url, response);
-            Ok::<_, reqwest::Error>(())
-        });
-        tasks.push(task);
-    }
-
-    println!("Await all tasks to complete...");
-    for task in tasks {
-        let r = task.await;
-        println!("task Result:{:?}", r);
-    }
-
-    let r = results.lock().unwrap();
-    Ok(r.to_vec())
-}
-
-fn write_quotes_to_csv(quotes: &Vec<Quote>, output_filepath: &str) -> Result<(), Box<dyn Error>> {
-    let mut wtr = csv::Writer::from_path(output_filepath)?;
-    wtr.write_record(&[&"isin", &"name", &"ask", &"bid", &"currency"])?;
-    for quote in quotes {
-        wtr.write_record(&[&quote.isin, &quote.name, &quote.ask, &quote.bid, &quote.currency])?;
-    }
-    wtr.flush()?;
-    Ok(())
+    eprintln!("Exiting with code {}", outcome.exit_code());
+    process::exit(outcome.exit_code());
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
-    let fp = &args.source_fp;
+    if let Some(Command::Quote(_)) = &args.command {
+        reserve_stdout();
+    }
     let path = env::current_dir().unwrap();
 
-    println!("The current directory is {}", path.display());
-    println!("CLI Configuration: {:?}", args);
+    log!("The current directory is {}", path.display());
+    log!("CLI Configuration: {:?}", args);
 
     let isin_path_prefix = env::var("ISIN_PATH_PREFIX");
     let isin_path_prefix = match isin_path_prefix {
@@ -259,47 +97,67 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Err(_e)=> &args.output_fp_prefix,
         Ok(output_path_prefix) => &output_path_prefix.clone()
     };
-    println!("ENV Configuration: {isin_path_prefix}, {output_path_prefix}, {source_path}");
+    let config_path = env::var("CONFIG_PATH");
+    let config_path = match config_path {
+        Err(_e)=> &args.config,
+        Ok(config_path) => &config_path.clone()
+    };
+    log!("ENV Configuration: {isin_path_prefix}, {output_path_prefix}, {source_path}, {config_path}");
+
+    let naming: NamingScheme = args.naming.parse().unwrap_or_else(|e| config_error(e));
+    if let Some(Command::MigrateNames(migrate_args)) = &args.command {
+        return migrate::migrate_names(migrate_args, output_path_prefix, naming);
+    }
+    if let Some(Command::MergeShards) = &args.command {
+        let merged = runner::merge_pending_shards(output_path_prefix, &args.unchanged)?;
+        log!("[MERGE] {} observations written", merged.len());
+        return Ok(());
+    }
+    let paths = Paths {
+        isin_path_prefix: isin_path_prefix.to_string(),
+        source_path: source_path.to_string(),
+        output_path_prefix: output_path_prefix.to_string(),
+        config_path: config_path.to_string(),
+    };
+    if let Some(Command::Daemon(daemon_args)) = &args.command {
+        return daemon::daemon(&args, daemon_args, &paths).await;
+    }
+    let config = read_config_from_file(config_path).unwrap_or_else(|e| config_error(e));
+    config.validate().unwrap_or_else(|e| config_error(e));
+    let calendar = Arc::new(Calendar::from_config(&config).unwrap_or_else(|e| config_error(e)));
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        return bench::bench(bench_args, source_path, &config);
+    }
+    if let Some(Command::Probe(probe_args)) = &args.command {
+        return probe::probe(probe_args, source_path, &config).await;
+    }
+    let cancel = CancellationToken::new();
+    signals::watch_deadline(cancel.clone(), args.deadline.map(Duration::from_secs));
+    if let Some(Command::Quote(quote_args)) = &args.command {
+        let run_id = runner::run_id(chrono::Utc::now());
+        let scheduler = Arc::new(runner::build_scheduler(&args, &config, &run_id, &calendar, cancel)?);
+        let outcome = quote::quote(&scheduler, quote_args, source_path, &args.output_format, runner::columns(&args)).await?;
+        exit_with(&outcome);
+        return Ok(());
+    }
 
     // System check
-    let sources = read_sources_from_file(&fp);
-    println!("Sources: {:?}", sources);
-    for source in sources {
-        println!(
-            "\n----------------------\nWorking on...{}\n----------------------\n",
-            source.site
-        );
-        let isins =
-            read_isins_from_file(&[isin_path_prefix, &source.site, ".txt"].concat().as_str());
-        let isins = match isins {
-            Err(e) => {
-                eprintln!("ISIN Read Error: {:?}", e);
-                continue;
-            }
-            Ok(isins) => isins,
-        };
-        let quotes = extract_quotes_from_source(&source, &isins).await;
-        let quotes = match quotes {
-            Err(e) => {
-                eprintln!("Get Data Error: {:?}", e);
-                continue;
-            }
-            Ok(quotes) => quotes,
-        };
-        println!("Quotes: {:?}", quotes);
-        // Write results to CSV
-        let csv_filepath = [
-            output_path_prefix,
-            &source.site,
-            &chrono::offset::Local::now()
-                .format("-%Y-%m-%d-%H-%M-%S")
-                .to_string(),
-            ".csv",
-        ]
-        .concat();
-        println!("> Writing quotes to {}", csv_filepath);
-        let _ = fs::create_dir_all(&output_path_prefix);
-        write_quotes_to_csv(&quotes, &csv_filepath)?;
+    let sources = read_sources_from_file(source_path).unwrap_or_else(|e| config_error(format!("{}: {}", source_path, e)));
+    extractors::check_rules(&sources, &config).unwrap_or_else(|e| config_error(e));
+    // Cloud Run keeps the execution name across the retries of a job
+    let slot = args.slot.clone().or(env::var("CLOUD_RUN_EXECUTION").ok());
+    let task_env = |name: &str| env::var(name).ok().and_then(|value| value.parse::<usize>().ok());
+    let shard = Shard::new(
+        args.task_index.or(task_env("CLOUD_RUN_TASK_INDEX")).unwrap_or(0),
+        args.task_count.or(task_env("CLOUD_RUN_TASK_COUNT")).unwrap_or(1),
+    )
+    .unwrap_or_else(|e| config_error(e));
+    if shard.is_some() && slot.is_none() {
+        config_error("sharded runs need a slot (--slot or CLOUD_RUN_EXECUTION)");
     }
+    let run_slot = RunSlot { slot, shard };
+    let reports = runner::run_sources(&args, &paths, &config, &calendar, sources, &run_slot, cancel).await?;
+    let outcome = outcome::evaluate(&reports, &config);
+    exit_with(&outcome);
     Ok(())
 }
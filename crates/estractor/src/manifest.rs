@@ -0,0 +1,33 @@
+use std::error::Error;
+use std::fs::File;
+use std::io;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::definitions::types::{OutputFile, SkippedIsin};
+use crate::writers::write_atomic;
+
+// summary of one run, written next to the output files
+#[derive(Debug, Serialize)]
+pub struct RunManifest {
+    pub run_id: String,
+    pub version: &'static str,
+    pub started_at: String,
+    pub finished_at: String,
+    pub config: serde_json::Value,
+    pub files: Vec<OutputFile>,
+    pub skipped: Vec<SkippedIsin>,
+}
+
+pub fn file_sha256(path: &str) -> Result<String, io::Error> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn write_manifest(output_path_prefix: &str, manifest: &RunManifest) -> Result<String, Box<dyn Error>> {
+    let manifest_path = [output_path_prefix, "manifest-", &manifest.run_id, ".json"].concat();
+    write_atomic(&manifest_path, serde_json::to_string_pretty(manifest)?.as_bytes())?;
+    Ok(manifest_path)
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::{FixedOffset, Utc};
+use chrono_tz::Tz;
+use common::naming::{NamingScheme, ObsFile, parse_file_name, parse_offset};
+
+use crate::definitions::args::MigrateArgs;
+
+// rename observation files written with the legacy local-time names to `scheme`
+pub fn migrate_names(args: &MigrateArgs, output_path_prefix: &str, scheme: NamingScheme) -> Result<(), Box<dyn Error>> {
+    let dir = args.dir.as_deref().unwrap_or(output_path_prefix);
+    let legacy_tz = LegacyTz::parse(&args.legacy_tz)?;
+    let mut renamed = 0;
+    for entry in fs::read_dir(dir)? {
+        let file_name = entry?.file_name().into_string().unwrap_or_default();
+        let Some(obs) = legacy_tz.parse_file_name(&file_name).filter(|obs| obs.legacy) else {
+            continue;
+        };
+        let ext = file_name.rsplit_once('.').map(|(_stem, ext)| ext).unwrap_or("csv");
+        let new_name = scheme.file_name(&obs.source, obs.at.with_timezone(&Utc), ext);
+        let (from, to) = (Path::new(dir).join(&file_name), Path::new(dir).join(&new_name));
+        if to.exists() {
+            eprintln!("[MIGRATE] {} already exists, keeping {}", new_name, file_name);
+            continue;
+        }
+        println!("[MIGRATE] {} -> {}", file_name, new_name);
+        if !args.dry_run {
+            fs::rename(from, to)?;
+        }
+        renamed += 1;
+    }
+    println!("[MIGRATE] {} files {}", renamed, if args.dry_run { "to rename" } else { "renamed" });
+    Ok(())
+}
+
+// a named timezone follows DST, so names written across a DST change convert to the right instant
+enum LegacyTz {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl LegacyTz {
+    fn parse(tz: &str) -> Result<Self, String> {
+        match tz.parse::<Tz>() {
+            Ok(tz) => Ok(LegacyTz::Named(tz)),
+            Err(_e) => parse_offset(tz)
+                .map(LegacyTz::Fixed)
+                .ok_or(format!("invalid timezone: {} (IANA name or +HH:MM)", tz)),
+        }
+    }
+
+    fn parse_file_name(&self, file_name: &str) -> Option<ObsFile> {
+        match self {
+            LegacyTz::Named(tz) => parse_file_name(file_name, tz),
+            LegacyTz::Fixed(offset) => parse_file_name(file_name, offset),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::definitions::config::Config;
+use crate::definitions::globals::{EXIT_FAILURE, EXIT_PARTIAL};
+use crate::definitions::types::SourceReport;
+
+// how a run went, decides the exit code Cloud Run retries and alerts on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Ok,
+    Partial(Vec<String>),
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Ok => 0,
+            Outcome::Partial(_) => EXIT_PARTIAL,
+            Outcome::Failed(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+// ISINs without a quote, cancelled ones included
+fn failure_rate(isins: usize, quotes: usize) -> f64 {
+    if isins == 0 {
+        return 0.0;
+    }
+    isins.saturating_sub(quotes) as f64 / isins as f64
+}
+
+// why one source counts as partial, None when it is within its threshold
+pub fn source_issue(report: &SourceReport, config: &Config) -> Option<String> {
+    if let Some(error) = &report.error {
+        return Some(format!("{}: not run, {}", report.site, error));
+    }
+    let threshold = config.source_threshold(&report.site);
+    let rate = failure_rate(report.isins, report.quotes);
+    if rate > threshold {
+        return Some(format!(
+            "{}: {} of {} ISINs failed ({:.0}% > {:.0}%)",
+            report.site,
+            report.isins - report.quotes,
+            report.isins,
+            rate * 100.0,
+            threshold * 100.0
+        ));
+    }
+    if report.blocked > 0 {
+        return Some(format!("{}: blocked on {} ISINs", report.site, report.blocked));
+    }
+    if let Some(skipped) = report.skipped.first() {
+        return Some(format!("{}: {} ISINs skipped, {}", report.site, report.skipped.len(), skipped.reason));
+    }
+    if report.cancelled > 0 {
+        return Some(format!("{}: {} ISINs cancelled", report.site, report.cancelled));
+    }
+    None
+}
+
+// the run fails when no source ran, nothing was quoted or too many ISINs failed overall,
+// it is partial when some source did not run, is over its own threshold or was cancelled
+pub fn evaluate(reports: &[SourceReport], config: &Config) -> Outcome {
+    if !reports.is_empty() && reports.iter().all(|r| r.error.is_some()) {
+        let errors: Vec<&str> = reports.iter().filter_map(|r| r.error.as_deref()).collect();
+        return Outcome::Failed(format!("no source could run: {}", errors.join("; ")));
+    }
+    let isins: usize = reports.iter().map(|r| r.isins).sum();
+    let quotes: usize = reports.iter().map(|r| r.quotes).sum();
+    if isins > 0 && quotes == 0 {
+        return Outcome::Failed(format!("none of {} ISINs was quoted", isins));
+    }
+    let rate = failure_rate(isins, quotes);
+    if rate > config.thresholds.run {
+        return Outcome::Failed(format!(
+            "{} of {} ISINs failed ({:.0}% > {:.0}%)",
+            isins - quotes,
+            isins,
+            rate * 100.0,
+            config.thresholds.run * 100.0
+        ));
+    }
+    let issues: Vec<String> = reports.iter().filter_map(|r| source_issue(r, config)).collect();
+    if issues.is_empty() { Outcome::Ok } else { Outcome::Partial(issues) }
+}
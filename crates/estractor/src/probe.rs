@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fs;
+use std::time::Instant;
+
+use crate::blocking::BlockDetector;
+use crate::definitions::args::ProbeArgs;
+use crate::definitions::config::Config;
+use crate::definitions::types::Extraction;
+use crate::extractors::{EXTRACTORS, extract};
+use crate::fetcher::fetch;
+use crate::http::read_body;
+use crate::readers::read_sources_from_file;
+use crate::session::source_client;
+use crate::streaming::StreamScan;
+
+fn print_extraction(extraction: Extraction) {
+    println!("rule:    {}", extraction.strategy);
+    println!("matched: {}", extraction.matched);
+    println!("raw:     {:?}", extraction.raw);
+    println!("value:   {}", extraction.value);
+    if let Some(bid) = extraction.bid {
+        println!("bid:     {}", bid);
+    }
+    if let Some(currency) = extraction.currency {
+        println!("currency: {}", currency);
+    }
+}
+
+pub async fn probe(args: &ProbeArgs, source_path: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let detector = BlockDetector::from_config(config)?;
+    let sources = read_sources_from_file(source_path).map_err(|e| format!("{}: {}", source_path, e))?;
+    let source = sources
+        .iter()
+        .find(|s| s.site == args.site.trim())
+        .ok_or(format!("site {} not found in {}", args.site, source_path))?;
+    println!("\n--> probe for Source: {:?}", source);
+
+    let start = Instant::now();
+    let html_content = match &args.from_file {
+        Some(page_path) => {
+            println!("> Reading page from {}", page_path);
+            fs::read_to_string(page_path)?
+        }
+        None => {
+            let url = [source.base_url.as_str(), args.isin.trim()].concat();
+            let http = config.http(&source.site);
+            let client = source_client(source, &http, config.session(&source.site)).await?;
+            let response = fetch(&client, &url).await?;
+            println!("> Status: {}", response.status());
+            read_body(response, http.max_response_bytes).await?
+        }
+    };
+    println!(
+        "> Page: {} bytes ({}) in {:?}",
+        html_content.len(),
+        source.content_type,
+        start.elapsed()
+    );
+    if let Some(signature) = detector.detect(&html_content, &source.site) {
+        println!("> Blocked: page matches the {} signature", signature);
+    }
+
+    let rule = config.extraction_rule(&source.site);
+    for extractor in EXTRACTORS {
+        let configured = if extractor == source.extractor { " (configured)" } else { "" };
+        println!("\n----------------------\n{}{}\n----------------------", extractor, configured);
+        let start = Instant::now();
+        let extraction = extract(extractor, &html_content, &source.site, &rule);
+        let elapsed = start.elapsed();
+        match extraction {
+            Ok(extraction) => print_extraction(extraction),
+            Err(e) => println!("error:   {}", e),
+        }
+        println!("time:    {:?}", elapsed);
+    }
+
+    // what a run writes when streaming is on: the scan, or the DOM extractor above when it gives up
+    let streaming = if config.streaming(&source.site) { "on" } else { "off" };
+    println!("\n----------------------\n{} streamed (streaming {})\n----------------------", source.extractor, streaming);
+    let start = Instant::now();
+    let streamed = StreamScan::new(&source.extractor, &source.site, &rule).map(|mut scan| {
+        scan.feed(&html_content);
+        scan.finish()
+    });
+    let elapsed = start.elapsed();
+    match streamed {
+        Some(Ok(extraction)) => print_extraction(extraction),
+        Some(Err(e)) => println!("error:   {}, the run uses the DOM extractor", e),
+        None => println!("error:   rule cannot be streamed, the run uses the DOM extractor"),
+    }
+    println!("time:    {:?}", elapsed);
+    Ok(())
+}
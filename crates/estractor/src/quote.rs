@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
+
+use crate::definitions::args::QuoteArgs;
+use crate::definitions::types::Isin;
+use crate::outcome::Outcome;
+use crate::readers::read_sources_from_file;
+use crate::scheduler::Scheduler;
+use crate::utils::log;
+use crate::writers::{Columns, QuoteWriter};
+
+// one ISIN per line, optionally followed by ", name"
+fn read_isin_list(reader: impl BufRead) -> Result<Vec<Isin>, io::Error> {
+    let mut isins = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let mut cols = line.split(",");
+        let isin = cols.next().unwrap_or("").trim();
+        if isin.is_empty() {
+            continue;
+        }
+        isins.push(Isin {
+            isin: isin.to_string(),
+            name: cols.next().unwrap_or("").trim().to_string(),
+        });
+    }
+    Ok(isins)
+}
+
+pub async fn quote(
+    scheduler: &Arc<Scheduler>,
+    args: &QuoteArgs,
+    source_path: &str,
+    format: &str,
+    columns: Columns,
+) -> Result<Outcome, Box<dyn Error>> {
+    let sources = read_sources_from_file(source_path).map_err(|e| format!("{}: {}", source_path, e))?;
+    let source = sources
+        .into_iter()
+        .find(|s| s.site == args.site.trim())
+        .ok_or(format!("site {} not found in {}", args.site, source_path))?;
+
+    let mut isins = read_isin_list(args.isins.join("\n").as_bytes())?;
+    match args.isins_from.as_deref() {
+        Some("-") => isins.extend(read_isin_list(io::stdin().lock())?),
+        Some(isin_path) => isins.extend(read_isin_list(BufReader::new(File::open(isin_path)?))?),
+        None => {}
+    }
+    if isins.is_empty() {
+        return Err("no ISINs given".into());
+    }
+
+    let total = isins.len();
+    let mut tasks = JoinSet::new();
+    for isin in isins {
+        let scheduler = scheduler.clone();
+        let source = source.clone();
+        tasks.spawn(async move { scheduler.fetch_quote(&source, &isin).await });
+    }
+
+    // write each quote as soon as its request completes
+    let mut wtr = QuoteWriter::new(io::stdout().lock(), format, columns)?;
+    let mut failed = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        match result? {
+            Ok(quote) => {
+                wtr.write(&quote)?;
+                wtr.flush()?;
+            }
+            Err(e) => {
+                log!("{}", e);
+                failed.push(e.to_string());
+            }
+        }
+    }
+    // scripts see a failure when nothing was quoted, partial when some ISINs were not
+    Ok(match failed.len() {
+        0 => Outcome::Ok,
+        n if n == total => Outcome::Failed(format!("none of {} ISINs was quoted", total)),
+        n => Outcome::Partial(vec![format!("{}: {} of {} ISINs not quoted", source.site, n, total)]),
+    })
+}
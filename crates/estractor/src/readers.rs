@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind, prelude::*};
+use std::path::Path;
+
+use crate::definitions::config::Config;
+use crate::definitions::types::*;
+use crate::utils::log;
+
+// an unreadable file is an error for the caller, the daemon keeps its previous sources
+pub fn read_sources_from_file(source_path: &str) -> Result<Vec<Source>, std::io::Error> {
+    let path = Path::new(source_path);
+
+    // Open the path in read-only mode, returns `io::Result<File>`
+    let file = File::open(path)?;
+    let mut start = false;
+    let reader = BufReader::new(file);
+    let mut sources: Vec<Source> = Vec::new();
+
+    for line_result in reader.lines() {
+        let line = line_result?;
+        let line = line.trim(); // Remove leading and trailing whitespace
+        if line.is_empty() {
+            continue;
+        }
+        if start {
+            if line.contains("-- END") {
+                start = false;
+            } else {
+                let cols = line.split(",");
+                let collection = cols.collect::<Vec<&str>>();
+                if collection.len() == 4 {
+                    log!("SOURCE: {:?}", collection);
+                    sources.push(Source {
+                        site: collection[0].trim().to_string(),
+                        content_type: collection[1].trim().to_string(),
+                        extractor: collection[2].trim().to_string(),
+                        base_url: collection[3].trim().to_string(),
+                    });
+                } else {
+                    log!("Source Error: {}", line);
+                }
+                // dbg!(collection);
+            }
+        } else {
+            start = line.contains("-- START");
+        }
+    }
+    Ok(sources)
+}
+
+pub fn read_isins_from_file(isin_path: &str) -> Result<Vec<Isin>, std::io::Error> {
+    //let path = env::current_dir().unwrap();
+    let path = Path::new(isin_path);
+
+    // Open the path in read-only mode, returns `io::Result<File>`
+    let file = File::open(path)?;
+    let mut start = false;
+    let reader = BufReader::new(file);
+    let mut isins: Vec<Isin> = Vec::new();
+
+    for line_result in reader.lines() {
+        let line = line_result?;
+        let line = line.trim(); // Remove leading and trailing whitespace
+        if line.is_empty() {
+            continue;
+        }
+        if start {
+            if line.contains("-- END") {
+                start = false;
+            } else {
+                let line = line.split(",").collect::<Vec<&str>>();
+                if line.len() < 2 {
+                    log!("[ISIN] discarding line: {:?}", line);
+                    continue;
+                }
+                isins.push(Isin {
+                    isin: line[0].trim().to_string(),
+                    name: line[1].trim().to_string(),
+                });
+            }
+        } else {
+            start = line.contains("-- START");
+        }
+    }
+    Ok(isins)
+}
+
+// non-empty lines between START and END, for list files such as holidays
+pub fn read_block_from_file(block_path: &str) -> Result<Vec<String>, std::io::Error> {
+    let reader = BufReader::new(File::open(block_path)?);
+    let mut start = false;
+    let mut lines = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if start {
+            if line.contains("-- END") {
+                start = false;
+            } else {
+                lines.push(line.to_string());
+            }
+        } else {
+            start = line.contains("-- START");
+        }
+    }
+    Ok(lines)
+}
+
+// settings file is optional, a missing file means defaults
+pub fn read_config_from_file(config_path: &str) -> Result<Config, Box<dyn Error>> {
+    match fs::read_to_string(config_path) {
+        Ok(content) => Ok(toml::from_str(&content).map_err(|e| format!("{}: {}", config_path, e))?),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            log!("No config at {}, using defaults", config_path);
+            Ok(Config::default())
+        }
+        Err(e) => Err(format!("{}: {}", config_path, e).into()),
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::definitions::globals::ISIN_ROUTES;
+use crate::definitions::types::{Isin, Source};
+use crate::readers::read_isins_from_file;
+use crate::scheduler::Scheduler;
+use crate::utils::log;
+use crate::writers::write_atomic;
+
+// routes file has the same layout as an ISIN file: "isin, site" between START and END
+fn read_routes(routes_path: &str) -> HashMap<String, String> {
+    match read_isins_from_file(routes_path) {
+        Ok(routes) => routes.into_iter().map(|r| (r.isin, r.name)).collect(),
+        Err(_e) => HashMap::new(),
+    }
+}
+
+fn write_routes(routes_path: &str, routes: &HashMap<String, String>) -> Result<(), std::io::Error> {
+    let mut isins: Vec<&String> = routes.keys().collect();
+    isins.sort();
+    let mut content = String::from("-- START\n");
+    for isin in isins {
+        content.push_str(&format!("{}, {}\n", isin, routes[isin]));
+    }
+    content.push_str("-- END\n");
+    write_atomic(routes_path, content.as_bytes())
+}
+
+fn guess_site(isin: &str) -> Option<&'static str> {
+    let isin = isin.trim().to_uppercase();
+    ISIN_ROUTES
+        .iter()
+        .find(|(prefix, _site)| isin.starts_with(prefix))
+        .map(|(_prefix, site)| *site)
+}
+
+// try the sources in turn and keep the first one that returns a price
+async fn probe_site(scheduler: &Scheduler, sources: &[Source], isin: &Isin) -> Option<String> {
+    for source in sources {
+        match scheduler.fetch_quote(source, isin).await {
+            Ok(_quote) => return Some(source.site.clone()),
+            Err(e) => log!("[ROUTE] {} not on {}: {}", isin.isin, source.site, e),
+        }
+    }
+    None
+}
+
+// watchlist ISINs by source, and the ones no source was found for
+pub struct Routed {
+    pub by_site: HashMap<String, Vec<Isin>>,
+    pub unrouted: Vec<Isin>,
+}
+
+// split a mixed watchlist by source: remembered route, then ISIN prefix, then probing
+pub async fn route_watchlist(
+    scheduler: &Scheduler,
+    sources: &[Source],
+    watchlist: Vec<Isin>,
+    routes_path: &str,
+) -> Result<Routed, Box<dyn Error>> {
+    let mut routes = read_routes(routes_path);
+    let is_source = |site: &str| sources.iter().any(|s| s.site == site);
+    let mut routed = Routed {
+        by_site: HashMap::new(),
+        unrouted: Vec::new(),
+    };
+
+    for isin in watchlist {
+        let site = match routes.get(&isin.isin).filter(|site| is_source(site)) {
+            Some(site) => Some(site.clone()),
+            None => match guess_site(&isin.isin).filter(|site| is_source(site)) {
+                Some(site) => Some(site.to_string()),
+                None => probe_site(scheduler, sources, &isin).await,
+            },
+        };
+        match site {
+            Some(site) => {
+                log!("[ROUTE] {} -> {}", isin.isin, site);
+                routes.insert(isin.isin.clone(), site.clone());
+                routed.by_site.entry(site).or_default().push(isin);
+            }
+            None => {
+                eprintln!("[ROUTE] no source found for {}", isin.isin);
+                routed.unrouted.push(isin);
+            }
+        }
+    }
+
+    write_routes(routes_path, &routes)?;
+    Ok(routed)
+}
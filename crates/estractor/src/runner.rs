@@ -0,0 +1,462 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use common::markers::{END_MARKER, PARTIAL_MARKER, UNCHANGED_MARKER, unchanged_line};
+use common::naming::{NamingScheme, obs_file_name};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::blocking::BlockDetector;
+use crate::cache::HttpCache;
+use crate::calendar::Calendar;
+use crate::dedup;
+use crate::definitions::args::Args;
+use crate::definitions::config::Config;
+use crate::definitions::globals::*;
+use crate::definitions::types::*;
+use crate::lock;
+use crate::manifest;
+use crate::readers::read_isins_from_file;
+use crate::routing;
+use crate::scheduler::Scheduler;
+use crate::http::client_builder;
+use crate::shards::{Merged, merge_shards, shard_dir, shard_path, unmerged_shards, write_empty_shard};
+use crate::slots::slot_obsdatetime;
+use crate::utils::log;
+use crate::writers::{ClosedFile, Columns, recover_temp_files, spawn_quote_writer};
+
+pub fn run_id(started_at: DateTime<Utc>) -> String {
+    format!("{}-{}", started_at.format("%Y%m%dT%H%M%SZ"), std::process::id())
+}
+
+pub fn columns(args: &Args) -> Columns {
+    Columns {
+        provenance: args.provenance,
+        session: args.sessions == "tag",
+    }
+}
+
+// scheduler of one run, cancelling `cancel` stops its pending requests
+pub fn build_scheduler(
+    args: &Args,
+    config: &Config,
+    run_id: &str,
+    calendar: &Arc<Calendar>,
+    cancel: CancellationToken,
+) -> Result<Scheduler, String> {
+    let detector = BlockDetector::from_config(config)?;
+    let client = client_builder(&config.http)?.build().map_err(|e| e.to_string())?;
+    let mut scheduler = Scheduler::new(client, args.max_per_host, cancel)
+        .with_blocking(detector)
+        .with_source_clients(config)
+        .with_extraction(config);
+    if args.provenance {
+        scheduler = scheduler.with_provenance(run_id);
+    }
+    if columns(args).session {
+        scheduler = scheduler.with_sessions(calendar.clone());
+    }
+    Ok(scheduler)
+}
+
+// commit a closed observation file as the unchanged mode says,
+// returns the file as listed in the manifest (None when nothing was written) and the changed count
+fn finish_observation(
+    closed: &ClosedFile,
+    site: &str,
+    marker: &str,
+    unchanged: &str,
+) -> Result<(Option<OutputFile>, Option<usize>), String> {
+    let csv_filepath = &closed.output_filepath;
+    let write_error = |e: String| format!("{}: {}", csv_filepath, e);
+    // partial runs are always kept, they say something the previous observation does not
+    let comparison = match unchanged {
+        "write" => None,
+        _ if marker == PARTIAL_MARKER => None,
+        _ => dedup::compare_with_latest(&closed.tmp_path, csv_filepath, site),
+    };
+    // marker of the committed file, None when nothing was written
+    let written = match &comparison {
+        Some(comparison) if comparison.unchanged() && unchanged == "skip" => {
+            log!("> {} unchanged since {}, not written", site, comparison.previous);
+            closed.discard().map_err(|e| write_error(e.to_string()))?;
+            // left by an earlier attempt of the same slot
+            if fs::remove_file(csv_filepath).is_ok() {
+                log!("> removed {} of an earlier attempt", csv_filepath);
+            }
+            None
+        }
+        Some(comparison) if comparison.unchanged() => {
+            log!("> {} unchanged since {}, writing marker", site, comparison.previous);
+            closed
+                .replace(&format!("{}\n", unchanged_line(&comparison.previous)))
+                .map_err(|e| write_error(e.to_string()))?;
+            Some(UNCHANGED_MARKER)
+        }
+        _ => {
+            closed.commit().map_err(|e| write_error(e.to_string()))?;
+            Some(marker)
+        }
+    };
+    let output = match written {
+        Some(marker) => Some(OutputFile {
+            site: site.to_string(),
+            sha256: manifest::file_sha256(csv_filepath).map_err(|e| write_error(e.to_string()))?,
+            file: csv_filepath.clone(),
+            rows: if marker == UNCHANGED_MARKER { 0 } else { closed.rows },
+            marker: marker.to_string(),
+        }),
+        None => None,
+    };
+    Ok((output, comparison.map(|c| c.changed)))
+}
+
+// commit this task's shard, then merge the observation if every shard is there
+fn finish_shard(
+    closed: &ClosedFile,
+    shard: &Shard,
+    site: &str,
+    options: &RunOptions,
+) -> Result<(Option<OutputFile>, Option<usize>), String> {
+    closed.commit().map_err(|e| format!("{}: {}", closed.output_filepath, e))?;
+    log!("> Shard {} of {} written", shard.name(), site);
+    merge_observation(shard, site, options)
+}
+
+fn merge_observation(
+    shard: &Shard,
+    site: &str,
+    options: &RunOptions,
+) -> Result<(Option<OutputFile>, Option<usize>), String> {
+    let shard_dir = shard_dir(&options.output_path_prefix, site, &options.obsdatetime);
+    let output_filepath = [
+        options.output_path_prefix.as_str(),
+        &obs_file_name(site, &options.obsdatetime, "csv"),
+    ]
+    .concat();
+    match merge_shards(&shard_dir, shard.count, &output_filepath)? {
+        Some(merged) => {
+            log!("> All {} shards of {} are in, merged into {}", shard.count, site, output_filepath);
+            finish_merged(merged, site, &options.unchanged)
+        }
+        None => {
+            log!("> Other shards of {} still running or merging, the last one merges", site);
+            Ok((None, None))
+        }
+    }
+}
+
+// commit a merged observation, then drop its shards; on an error they are kept for the next run
+fn finish_merged(merged: Merged, site: &str, unchanged: &str) -> Result<(Option<OutputFile>, Option<usize>), String> {
+    let finished = if merged.marker == EMPTY_SHARD_MARKER {
+        // an empty observation would hide the latest real one of the source
+        log!("> No watchlist ISIN routed to {} in any shard, not written", site);
+        merged.file.discard().map_err(|e| format!("{}: {}", merged.file.tmp_path, e))?;
+        (None, None)
+    } else {
+        finish_observation(&merged.file, site, &merged.marker, unchanged)?
+    };
+    merged.remove_shards();
+    Ok(finished)
+}
+
+// merge the complete shard directories a crashed or separate task left behind
+pub fn merge_pending_shards(output_path_prefix: &str, unchanged: &str) -> Result<Vec<OutputFile>, String> {
+    let mut outputs = Vec::new();
+    for pending in unmerged_shards(output_path_prefix) {
+        match merge_shards(&pending.shard_dir, pending.count, &pending.output_filepath)? {
+            Some(merged) => {
+                log!("[MERGE] {} shards into {}", pending.count, pending.output_filepath);
+                let (output, _changed) = finish_merged(merged, &pending.site, unchanged)?;
+                outputs.extend(output);
+            }
+            None => log!("[MERGE] {} is missing shards, not merged", pending.shard_dir),
+        }
+    }
+    Ok(outputs)
+}
+
+// extract and write one source, returns the timing report
+async fn run_source(
+    scheduler: Arc<Scheduler>,
+    source: Source,
+    isins: Vec<Isin>,
+    options: RunOptions,
+    max_failures: usize,
+) -> Result<SourceReport, String> {
+    let start = Instant::now();
+    log!(
+        "\n----------------------\nWorking on...{}\n----------------------\n",
+        source.site
+    );
+    // Write results to CSV as they arrive
+    let csv_filepath = match &options.shard {
+        Some(shard) => {
+            let shard_dir = shard_dir(&options.output_path_prefix, &source.site, &options.obsdatetime);
+            fs::create_dir_all(&shard_dir).map_err(|e| format!("{}: {}", shard_dir, e))?;
+            shard_path(&shard_dir, shard)
+        }
+        None => [
+            options.output_path_prefix.as_str(),
+            &obs_file_name(&source.site, &options.obsdatetime, "csv"),
+        ]
+        .concat(),
+    };
+    log!("> Writing quotes to {}", csv_filepath);
+    let write_error = |e: String| format!("{}: {}", csv_filepath, e);
+    let (tx, writer) = spawn_quote_writer(&csv_filepath, options.columns).map_err(|e| write_error(e.to_string()))?;
+    let extracted = scheduler.extract_quotes_from_source(&source, &isins, tx, max_failures).await;
+    let writer = writer.await.map_err(|e| write_error(e.to_string()))?.map_err(write_error)?;
+    // ISINs never requested leave the observation incomplete, like a cancelled run
+    let complete = extracted.cancelled == 0 && extracted.skipped.is_empty();
+    let marker = if complete { END_MARKER } else { PARTIAL_MARKER };
+    let closed = writer.close(marker).map_err(|e| write_error(e.to_string()))?;
+    let (output, changed) = match &options.shard {
+        Some(shard) => finish_shard(&closed, shard, &source.site, &options)?,
+        None => finish_observation(&closed, &source.site, marker, &options.unchanged)?,
+    };
+    Ok(SourceReport {
+        output,
+        site: source.site,
+        isins: isins.len(),
+        quotes: closed.rows,
+        blocked: extracted.blocked,
+        not_modified: extracted.not_modified,
+        cancelled: extracted.cancelled,
+        skipped: extracted.skipped,
+        changed,
+        elapsed: start.elapsed(),
+        error: None,
+    })
+}
+
+// one run over `sources`: every source is written to its own file, then the manifest
+pub async fn run_sources(
+    args: &Args,
+    paths: &Paths,
+    config: &Config,
+    calendar: &Arc<Calendar>,
+    mut sources: Vec<Source>,
+    run_slot: &RunSlot,
+    cancel: CancellationToken,
+) -> Result<Vec<SourceReport>, String> {
+    let naming: NamingScheme = args.naming.parse()?;
+    let started_at = Utc::now();
+    // retries of a slot run under the slot id, their manifest replaces the previous one
+    let run_id = match (&run_slot.slot, &run_slot.shard) {
+        (Some(slot), Some(shard)) => format!("{}-{}", slot, shard.name()),
+        (Some(slot), None) => slot.clone(),
+        (None, Some(_shard)) => return Err("sharded runs need a slot (--slot or CLOUD_RUN_EXECUTION)".to_string()),
+        (None, None) => run_id(started_at),
+    };
+    // shards of one slot run side by side, each one only excludes its own retries
+    let lock_file = match &run_slot.shard {
+        Some(shard) => format!("estractor-{}.lock", shard.name()),
+        None => LOCK_FILE.to_string(),
+    };
+    // held until the manifest is written
+    let lock = lock::acquire(
+        &paths.output_path_prefix,
+        &lock_file,
+        &run_id,
+        &args.lock,
+        Duration::from_secs(args.lock_ttl),
+        Duration::from_secs(args.lock_wait),
+        &cancel,
+    );
+    let Some(_lock) = lock.await? else {
+        return Ok(Vec::new());
+    };
+    let obsdatetime = match &run_slot.slot {
+        Some(slot) => slot_obsdatetime(&paths.output_path_prefix, slot, &naming.obsdatetime(started_at)).await?,
+        None => naming.obsdatetime(started_at),
+    };
+    // one cache per lock, next to it
+    let cache_path = match &run_slot.shard {
+        Some(shard) => format!("{}http-cache-{}.json", paths.output_path_prefix, shard.name()),
+        None => [paths.output_path_prefix.as_str(), HTTP_CACHE_FILE].concat(),
+    };
+    let scheduler = build_scheduler(args, config, &run_id, calendar, cancel)?.with_http_cache(HttpCache::load(&cache_path));
+    let scheduler = Arc::new(scheduler);
+
+    if args.sessions == "skip" {
+        sources.retain(|source| match calendar.in_session(&source.site, started_at) {
+            Some(false) => {
+                log!("{}: venue {} is closed, skipping", source.site, calendar.venue(&source.site).unwrap().name);
+                false
+            }
+            _ => true,
+        });
+    }
+    log!("Sources: {:?}", sources);
+    let _ = fs::create_dir_all(&paths.output_path_prefix);
+    // with the lock held no earlier attempt is still writing, its lease outlived its last write
+    let min_age = Duration::from_secs(args.lock_ttl) / 2;
+    match &run_slot.shard {
+        Some(shard) => {
+            let own_file = [shard.name().as_str(), ".csv"].concat();
+            let shards_root = [paths.output_path_prefix.as_str(), SHARDS_DIR].concat();
+            for dir in fs::read_dir(&shards_root).into_iter().flatten().filter_map(|entry| entry.ok()) {
+                recover_temp_files(&dir.path().to_string_lossy(), |name| name == own_file, min_age);
+            }
+        }
+        None => recover_temp_files(&paths.output_path_prefix, |_name| true, min_age),
+    }
+    let mut routed = match &args.watchlist {
+        Some(watchlist_path) => {
+            // one routes file per shard, like the cache
+            let routes_path = match &run_slot.shard {
+                Some(shard) => format!("{}routes-{}.txt", paths.output_path_prefix, shard.name()),
+                None => [paths.output_path_prefix.as_str(), ROUTES_FILE].concat(),
+            };
+            let watchlist = read_isins_from_file(watchlist_path).map_err(|e| format!("{}: {}", watchlist_path, e))?;
+            // each shard only routes its own part of the watchlist
+            let watchlist = match &run_slot.shard {
+                Some(shard) => watchlist.into_iter().filter(|isin| shard.owns_isin(&isin.isin)).collect(),
+                None => watchlist,
+            };
+            let routed = routing::route_watchlist(&scheduler, &sources, watchlist, &routes_path).await;
+            Some(routed.map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+
+    let options = RunOptions {
+        output_path_prefix: paths.output_path_prefix.clone(),
+        obsdatetime,
+        columns: columns(args),
+        unchanged: args.unchanged.clone(),
+        shard: run_slot.shard,
+    };
+
+    // all sources run concurrently, sharing the scheduler
+    let start = Instant::now();
+    let mut jobs = JoinSet::new();
+    let mut reports = Vec::new();
+    let mut write_error = None;
+    if let Some(routed) = &routed
+        && !routed.unrouted.is_empty()
+    {
+        reports.push(SourceReport::unrouted(&routed.unrouted));
+    }
+    for source in sources {
+        let isin_path = [paths.isin_path_prefix.as_str(), &source.site, ".txt"].concat();
+        let isins = match routed.as_mut() {
+            // the watchlist was split between the shards before routing
+            Some(routed) => match (routed.by_site.remove(&source.site), &options.shard) {
+                (Some(isins), _) => Ok(isins),
+                (None, Some(shard)) => {
+                    log!("> No watchlist ISIN of shard {} routed to {}", shard.name(), source.site);
+                    let shard_dir = shard_dir(&options.output_path_prefix, &source.site, &options.obsdatetime);
+                    match write_empty_shard(&shard_dir, shard).and_then(|_| merge_observation(shard, &source.site, &options)) {
+                        Ok((Some(output), changed)) => reports.push(SourceReport {
+                            output: Some(output),
+                            changed,
+                            ..SourceReport::empty(&source.site)
+                        }),
+                        Ok((None, _changed)) => {}
+                        Err(e) => {
+                            eprintln!("Write Error: {}", e);
+                            write_error = Some(e);
+                        }
+                    }
+                    continue;
+                }
+                // an empty observation would hide the latest real one of the source
+                (None, None) => {
+                    log!("> No watchlist ISIN routed to {}, not run", source.site);
+                    continue;
+                }
+            },
+            None => read_isins_from_file(&isin_path),
+        };
+        let isins = match isins {
+            Err(e) => {
+                eprintln!("ISIN Read Error: {}: {}", isin_path, e);
+                reports.push(SourceReport::failed(&source.site, format!("{}: {}", isin_path, e)));
+                continue;
+            }
+            Ok(isins) => isins,
+        };
+        let isins: Vec<Isin> = match (&run_slot.shard, &routed) {
+            (Some(shard), None) => isins.into_iter().filter(|isin| shard.owns(&source.site, &isin.isin)).collect(),
+            _ => isins,
+        };
+        let max_failures = config.breaker_threshold(&source.site);
+        jobs.spawn(run_source(
+            scheduler.clone(),
+            source,
+            isins,
+            options.clone(),
+            max_failures,
+        ));
+    }
+
+    while let Some(job) = jobs.join_next().await {
+        match job.map_err(|e| e.to_string())? {
+            Ok(report) => reports.push(report),
+            Err(e) => {
+                eprintln!("Write Error: {}", e);
+                write_error = Some(e);
+            }
+        }
+    }
+    if let Err(e) = scheduler.save_http_cache() {
+        eprintln!("[CACHE] {}", e);
+    }
+    reports.sort_by(|a, b| a.site.cmp(&b.site));
+    log!("\n----------------------\nRun completed in {:?}\n----------------------", start.elapsed());
+    for report in &reports {
+        if let Some(error) = &report.error {
+            log!("{}: not run, {}", report.site, error);
+            continue;
+        }
+        let mut changed = match report.changed {
+            Some(changed) => format!(", {} changed", changed),
+            None => String::new(),
+        };
+        if report.not_modified > 0 {
+            changed.push_str(&format!(", {} not modified", report.not_modified));
+        }
+        log!(
+            "{}: {}/{} quotes ({} failed, {} blocked, {} skipped, {} cancelled{}) in {:?}",
+            report.site,
+            report.quotes,
+            report.isins,
+            report.isins.saturating_sub(report.quotes + report.blocked + report.skipped.len() + report.cancelled),
+            report.blocked,
+            report.skipped.len(),
+            report.cancelled,
+            changed,
+            report.elapsed
+        );
+    }
+
+    let run_manifest = manifest::RunManifest {
+        run_id,
+        version: env!("CARGO_PKG_VERSION"),
+        started_at: started_at.to_rfc3339(),
+        finished_at: Utc::now().to_rfc3339(),
+        config: serde_json::json!({
+            "args": args,
+            "source_path": paths.source_path,
+            "isin_path_prefix": paths.isin_path_prefix,
+            "output_path_prefix": paths.output_path_prefix,
+            "config_path": paths.config_path,
+            "settings": config.redacted(),
+        }),
+        files: reports.iter().filter_map(|r| r.output.clone()).collect(),
+        skipped: reports.iter().flat_map(|r| r.skipped.clone()).collect(),
+    };
+    match manifest::write_manifest(&paths.output_path_prefix, &run_manifest) {
+        Ok(manifest_path) => log!("> Manifest written to {}", manifest_path),
+        Err(e) => eprintln!("Manifest Error: {}", e),
+    }
+
+    match write_error {
+        Some(e) => Err(e),
+        None => Ok(reports),
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use reqwest::{Client, Url};
+use tokio::sync::{OnceCell, Semaphore, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::calendar::Calendar;
+use crate::breaker::CircuitBreaker;
+use crate::cache::HttpCache;
+use crate::definitions::config::{Config, HttpConfig, SessionConfig};
+use crate::definitions::types::{ExtractionRule, Extracted, Isin, Quote, SkippedIsin, Source};
+use crate::blocking::BlockDetector;
+use crate::fetcher::{self, FetchError, FetchOptions};
+use crate::session;
+use crate::utils::log;
+
+// what became of the request for one ISIN
+enum Fetched {
+    Quote(Box<Quote>),
+    Failed(String),
+    Blocked(String),
+    Skipped(String), // circuit breaker open
+    Cancelled,
+}
+
+// one pooled client shared by all sources, with a limit of concurrent requests per host
+pub struct Scheduler {
+    pub client: Client,
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    cancel: CancellationToken,
+    provenance_run_id: Option<String>,
+    calendar: Option<Arc<Calendar>>,
+    detector: BlockDetector,
+    http: HttpConfig,
+    source_http: HashMap<String, HttpConfig>,        // by site, sources with their own settings
+    source_sessions: HashMap<String, SessionConfig>, // by site
+    clients: Mutex<HashMap<String, Arc<OnceCell<Client>>>>,
+    cache: Option<HttpCache>,
+    streaming: bool,
+    source_streaming: HashMap<String, bool>, // by site
+    rules: HashMap<String, ExtractionRule>,  // by site, from the config
+}
+
+impl Scheduler {
+    pub fn new(client: Client, max_per_host: usize, cancel: CancellationToken) -> Self {
+        Scheduler {
+            client,
+            max_per_host: max_per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+            cancel,
+            provenance_run_id: None,
+            calendar: None,
+            detector: BlockDetector::builtin(),
+            http: HttpConfig::default(),
+            source_http: HashMap::new(),
+            source_sessions: HashMap::new(),
+            clients: Mutex::new(HashMap::new()),
+            cache: None,
+            streaming: false,
+            source_streaming: HashMap::new(),
+            rules: HashMap::new(),
+        }
+    }
+
+    // sources with a session or their own http settings get their own client,
+    // the others share `client`, built from `config.http`
+    pub fn with_source_clients(mut self, config: &Config) -> Self {
+        self.http = config.http.clone();
+        for (site, source) in &config.sources {
+            if source.http.is_some() {
+                self.source_http.insert(site.clone(), config.http(site));
+            }
+            if let Some(session) = &source.session {
+                self.source_sessions.insert(site.clone(), session.clone());
+            }
+        }
+        self
+    }
+
+    // extraction rules and streaming as the config says, per source
+    pub fn with_extraction(mut self, config: &Config) -> Self {
+        self.streaming = config.extraction.streaming;
+        self.source_streaming = config.sources.keys().map(|site| (site.clone(), config.streaming(site))).collect();
+        self.rules = config.sources.keys().map(|site| (site.clone(), config.extraction_rule(site))).collect();
+        self
+    }
+
+    // conditional requests for the sources that allow them
+    pub fn with_http_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn save_http_cache(&self) -> Result<(), String> {
+        match &self.cache {
+            Some(cache) => cache.save(),
+            None => Ok(()),
+        }
+    }
+
+    fn http(&self, site: &str) -> &HttpConfig {
+        self.source_http.get(site).unwrap_or(&self.http)
+    }
+
+    // set up once per source and run, the first ISIN request waits for the preflight
+    async fn client_for(&self, source: &Source) -> Result<Client, String> {
+        let session = self.source_sessions.get(&source.site);
+        if session.is_none() && !self.source_http.contains_key(&source.site) {
+            return Ok(self.client.clone());
+        }
+        let cell = self.clients.lock().unwrap().entry(source.site.clone()).or_default().clone();
+        cell.get_or_try_init(|| session::source_client(source, self.http(&source.site), session))
+            .await
+            .cloned()
+    }
+
+    // challenge and consent page signatures from the config
+    pub fn with_blocking(mut self, detector: BlockDetector) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    // tag each quote as in-session or off-session for its source venue
+    pub fn with_sessions(mut self, calendar: Arc<Calendar>) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    // keep the provenance of each quote, tagged with the run id
+    pub fn with_provenance(mut self, run_id: &str) -> Self {
+        self.provenance_run_id = Some(run_id.to_string());
+        self
+    }
+
+    fn host_limit(&self, url: &str) -> Arc<Semaphore> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_default();
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+            .clone()
+    }
+
+    // the breaker is checked once the host allows the request, so that ISINs
+    // queued behind the failing ones are skipped without a request
+    async fn fetch_guarded(&self, source: &Source, isin: &Isin, breaker: Option<&CircuitBreaker>) -> Fetched {
+        let limit = self.host_limit(&source.base_url);
+        tokio::select! {
+            _ = self.cancel.cancelled() => Fetched::Cancelled,
+            fetched = async {
+                let _permit = match limit.acquire().await {
+                    Ok(permit) => permit,
+                    Err(e) => return Fetched::Failed(e.to_string()),
+                };
+                if let Some(reason) = breaker.and_then(|breaker| breaker.open_reason()) {
+                    return Fetched::Skipped(reason);
+                }
+                let client = match self.client_for(source).await {
+                    Ok(client) => client,
+                    Err(e) => return Fetched::Failed(e),
+                };
+                let http = self.http(&source.site);
+                let no_rule = ExtractionRule::default();
+                let options = FetchOptions {
+                    detector: &self.detector,
+                    rule: self.rules.get(&source.site).unwrap_or(&no_rule),
+                    max_response_bytes: http.max_response_bytes,
+                    cache: self.cache.as_ref().filter(|_cache| http.conditional),
+                    streaming: *self.source_streaming.get(&source.site).unwrap_or(&self.streaming),
+                };
+                let mut quote = match fetcher::fetch_quote(&client, source, isin, &options).await {
+                    Ok(quote) => quote,
+                    Err(FetchError::Blocked(e)) => {
+                        let reason = format!("blocked by {}", e);
+                        if let Some(breaker) = breaker {
+                            breaker.blocked(&reason);
+                        }
+                        return Fetched::Blocked(reason);
+                    }
+                    Err(FetchError::Failed(e)) => {
+                        if let Some(breaker) = breaker {
+                            breaker.failure(&e);
+                        }
+                        return Fetched::Failed(e);
+                    }
+                };
+                if let Some(breaker) = breaker {
+                    breaker.success();
+                }
+                match (&self.provenance_run_id, quote.provenance.as_mut()) {
+                    (Some(run_id), Some(provenance)) => provenance.run_id = run_id.clone(),
+                    _ => quote.provenance = None,
+                }
+                if let Some(calendar) = &self.calendar {
+                    quote.session = calendar.session_tag(&source.site, chrono::Utc::now());
+                }
+                Fetched::Quote(Box::new(quote))
+            } => fetched,
+        }
+    }
+
+    pub async fn fetch_quote(&self, source: &Source, isin: &Isin) -> Result<Quote, String> {
+        match self.fetch_guarded(source, isin, None).await {
+            Fetched::Quote(quote) => Ok(*quote),
+            Fetched::Failed(e) | Fetched::Blocked(e) | Fetched::Skipped(e) => Err(e),
+            Fetched::Cancelled => Err(format!("{}: cancelled", isin.isin)),
+        }
+    }
+
+    // sends each quote to `results` as soon as it is extracted, returns the ISINs
+    // blocked by a challenge page, skipped by the circuit breaker or cancelled before completion
+    pub async fn extract_quotes_from_source(
+        self: &Arc<Self>,
+        source: &Source,
+        isins: &[Isin],
+        results: mpsc::Sender<Quote>,
+        max_failures: usize,
+    ) -> Extracted {
+        log!("\n--> init for Source: {:?}", source);
+        let breaker = Arc::new(CircuitBreaker::new(&source.site, max_failures));
+        let not_modified = Arc::new(AtomicUsize::new(0));
+
+        // Vector to hold futures
+        let mut tasks = vec![];
+
+        for isin in isins {
+            log!("> ISIN: {} URL: {}{}", isin.isin, source.base_url, isin.isin);
+            let scheduler = Arc::clone(self);
+            let r = results.clone();
+            // Spawn async task for each request
+            let source = source.clone();
+            let isin = isin.clone();
+            let breaker = breaker.clone();
+            let not_modified = not_modified.clone();
+            let task = tokio::spawn(async move {
+                // quotes go to the writer, cancelled and skipped ISINs back to the caller
+                match scheduler.fetch_guarded(&source, &isin, Some(&breaker)).await {
+                    Fetched::Quote(quote) => {
+                        if quote.not_modified {
+                            not_modified.fetch_add(1, Ordering::Relaxed);
+                        }
+                        if r.send(*quote).await.is_err() {
+                            eprintln!("\nWriter closed, quote for {} dropped", isin.isin);
+                        }
+                        (isin, None)
+                    }
+                    Fetched::Failed(e) => {
+                        eprintln!("\n{}", e);
+                        (isin, None)
+                    }
+                    Fetched::Blocked(e) => {
+                        eprintln!("\n{}: {}", isin.isin, e);
+                        (isin, Some(Fetched::Blocked(e)))
+                    }
+                    unquoted => (isin, Some(unquoted)),
+                }
+            });
+            tasks.push(task);
+        }
+
+        log!("Await all tasks to complete...");
+        let mut extracted = Extracted::default();
+        for task in tasks {
+            match task.await {
+                Ok((_isin, Some(Fetched::Cancelled))) => extracted.cancelled += 1,
+                Ok((_isin, Some(Fetched::Blocked(_)))) => extracted.blocked += 1,
+                Ok((isin, Some(Fetched::Skipped(reason)))) => extracted.skipped.push(SkippedIsin {
+                    site: source.site.clone(),
+                    isin: isin.isin,
+                    reason,
+                }),
+                Ok((isin, _)) => log!("task Result: {} done", isin.isin),
+                Err(e) => eprintln!("task Error: {:?}", e),
+            }
+        }
+        extracted.not_modified = not_modified.load(Ordering::Relaxed);
+        if let Some(reason) = breaker.open_reason() {
+            log!("> {}: {} ISINs skipped, {}", source.site, extracted.skipped.len(), reason);
+        }
+        extracted
+    }
+}
@@ -0,0 +1,139 @@
+use std::rc::Rc;
+
+use ego_tree::NodeId;
+use regex::Regex;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope};
+use scraper::{ElementRef, Html, Selector};
+
+use crate::definitions::types::{Extraction, Script};
+use crate::utils::{log, price_formatter};
+
+// an element of the page as scripts see it
+#[derive(Clone)]
+struct Element {
+    document: Rc<Html>,
+    id: NodeId,
+}
+
+impl Element {
+    fn get(&self) -> ElementRef<'_> {
+        // ids only come from elements of the same document
+        ElementRef::wrap(self.document.tree.get(self.id).unwrap()).unwrap()
+    }
+
+    fn wrap(&self, element: ElementRef) -> Element {
+        Element {
+            document: self.document.clone(),
+            id: element.id(),
+        }
+    }
+}
+
+fn selector(css: &str) -> Result<Selector, Box<EvalAltResult>> {
+    Selector::parse(css).map_err(|e| format!("invalid selector {}: {}", css, e).into())
+}
+
+fn select(element: &mut Element, css: &str) -> Result<Array, Box<EvalAltResult>> {
+    let selector = selector(css)?;
+    Ok(element.get().select(&selector).map(|found| Dynamic::from(element.wrap(found))).collect())
+}
+
+// first match or ()
+fn first(element: &mut Element, css: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+    let selector = selector(css)?;
+    Ok(element.get().select(&selector).next().map_or(Dynamic::UNIT, |found| Dynamic::from(element.wrap(found))))
+}
+
+fn attr(element: &mut Element, name: &str) -> Dynamic {
+    element.get().value().attr(name).map_or(Dynamic::UNIT, |value| value.into())
+}
+
+// groups of the first match, the whole match first; empty when nothing matched
+fn capture(text: &str, pattern: &str) -> Result<Array, Box<EvalAltResult>> {
+    let re = Regex::new(pattern).map_err(|e| format!("invalid pattern {}: {}", pattern, e))?;
+    Ok(match re.captures(text) {
+        Some(captures) => captures.iter().map(|group| group.map_or("", |g| g.as_str()).into()).collect(),
+        None => Array::new(),
+    })
+}
+
+// "1.234,50" -> 1234.5, () when it is not a price
+fn price(text: &str) -> Dynamic {
+    price_formatter(text).parse::<f64>().map_or(Dynamic::UNIT, |price| price.into())
+}
+
+// no imports, no filesystem or network, and a bounded number of operations
+fn engine(max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(max_operations);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.on_print(|text| log!("[SCRIPT] {}", text));
+    engine.on_debug(|text, _source, position| log!("[SCRIPT] {} {}", position, text));
+    engine
+        .register_type_with_name::<Element>("Element")
+        .register_fn("select", select)
+        .register_fn("first", first)
+        .register_fn("attr", attr)
+        .register_get("text", |element: &mut Element| element.get().text().collect::<String>())
+        .register_get("html", |element: &mut Element| element.get().html())
+        .register_get("name", |element: &mut Element| element.get().value().name().to_string())
+        .register_fn("capture", capture)
+        .register_fn("price", price);
+    engine
+}
+
+pub fn compile(script: &Script) -> Result<(), String> {
+    engine(script.max_operations)
+        .compile(&script.code)
+        .map(|_ast| ())
+        .map_err(|e| format!("{}: {}", script.path, e))
+}
+
+fn field(value: Option<Dynamic>) -> Option<String> {
+    match value {
+        Some(value) if value.is_unit() => None,
+        Some(value) => Some(value.to_string()),
+        None => None,
+    }
+}
+
+// the script returns the ask price, or a map with ask and optionally bid and currency
+fn returned(result: Dynamic, script: &Script) -> Result<Extraction, &'static str> {
+    let matched = result.to_string();
+    let mut fields = match result.is_map() {
+        true => result.cast::<Map>(),
+        false => Map::from([("ask".into(), result)]),
+    };
+    let ask = field(fields.remove("ask")).ok_or("script returned no price")?;
+    Ok(Extraction {
+        matched,
+        value: price_formatter(&ask),
+        raw: ask.trim().to_string(),
+        strategy: script.path.clone(),
+        currency: field(fields.remove("currency")).map(|currency| currency.trim().to_string()),
+        bid: field(fields.remove("bid")).map(|bid| price_formatter(&bid)),
+    })
+}
+
+// runs the script with the page as `document` (an Element) and `html`, and the `site`
+pub fn run(script: &Script, html_content: &str, source_site: &str) -> Result<Extraction, &'static str> {
+    let failed = |e: String| {
+        eprintln!("[SCRIPT] {} ({}): {}", source_site, script.path, e);
+        "script failed"
+    };
+    let engine = engine(script.max_operations);
+    let ast = engine.compile(&script.code).map_err(|e| failed(e.to_string()))?;
+    let document = Rc::new(Html::parse_document(html_content));
+    let root = document.root_element().id();
+    let mut scope = Scope::new();
+    scope.push("document", Element { document, id: root });
+    scope.push_constant("html", html_content.to_string());
+    scope.push_constant("site", source_site.to_string());
+    let result = engine
+        .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+        .map_err(|e| failed(e.to_string()))?;
+    returned(result, script)
+}
@@ -0,0 +1,71 @@
+use std::env;
+use std::sync::Arc;
+
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Url};
+
+use crate::definitions::config::{HttpConfig, SessionConfig};
+use crate::definitions::types::Source;
+use crate::fetcher::fetch;
+use crate::http::client_builder;
+use crate::utils::log;
+
+fn headers(session: &SessionConfig) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &session.headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("header {}: {}", name, e))?;
+        let value = HeaderValue::from_str(value).map_err(|e| format!("header {}: {}", name, e))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+// cookies from the config, then from the environment so secrets stay out of the file
+fn cookie_jar(source: &Source, session: &SessionConfig) -> Result<Arc<Jar>, String> {
+    let url = Url::parse(&source.base_url).map_err(|e| format!("{}: {}", source.base_url, e))?;
+    let jar = Jar::default();
+    for (name, value) in &session.cookies {
+        jar.add_cookie_str(&format!("{}={}", name, value), &url);
+    }
+    for (name, var) in &session.cookies_env {
+        match env::var(var) {
+            Ok(value) => jar.add_cookie_str(&format!("{}={}", name, value), &url),
+            Err(_e) => eprintln!("[SESSION] {}: {} is not set, cookie {} left out", source.site, var, name),
+        }
+    }
+    Ok(Arc::new(jar))
+}
+
+// client of one source with its own cookie store, shared by all its ISIN requests
+pub fn build_session(source: &Source, http: &HttpConfig, session: &SessionConfig) -> Result<Client, String> {
+    client_builder(http)?
+        .cookie_provider(cookie_jar(source, session)?)
+        .default_headers(headers(session)?)
+        .build()
+        .map_err(|e| format!("{}: {}", source.site, e))
+}
+
+// landing pages that set the session cookies, a failed one is logged and the run goes on
+pub async fn preflight(client: &Client, source: &Source, session: &SessionConfig) {
+    for url in &session.preflight {
+        match fetch(client, url).await {
+            Ok(response) => log!("[SESSION] {}: preflight {} -> {}", source.site, url, response.status()),
+            Err(e) => eprintln!("[SESSION] {}: preflight {} failed: {}", source.site, url, e),
+        }
+    }
+}
+
+pub async fn open_session(source: &Source, http: &HttpConfig, session: &SessionConfig) -> Result<Client, String> {
+    let client = build_session(source, http, session)?;
+    preflight(&client, source, session).await;
+    Ok(client)
+}
+
+// client of a source with its own http settings, with or without a session
+pub async fn source_client(source: &Source, http: &HttpConfig, session: Option<&SessionConfig>) -> Result<Client, String> {
+    match session {
+        Some(session) => open_session(source, http, session).await,
+        None => client_builder(http)?.build().map_err(|e| format!("{}: {}", source.site, e)),
+    }
+}
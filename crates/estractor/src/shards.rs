@@ -0,0 +1,251 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+use common::markers::{END_MARKER, PARTIAL_MARKER};
+use common::naming::{obs_file_name, parse_file_name, utc_offset};
+use sha2::{Digest, Sha256};
+
+use crate::definitions::globals::{EMPTY_SHARD_MARKER, MERGE_MARKER, MERGE_MARKER_TTL, SHARDS_DIR};
+use crate::definitions::types::Shard;
+use crate::utils::log;
+use crate::writers::{ClosedFile, temp_path, write_atomic};
+
+impl Shard {
+    // task count 1 is the same as no sharding
+    pub fn new(index: usize, count: usize) -> Result<Option<Shard>, String> {
+        match (index, count) {
+            (_, 0) => Err("task count must be at least 1".to_string()),
+            (index, count) if index >= count => Err(format!("task index {} out of {} tasks", index, count)),
+            (_, 1) => Ok(None),
+            (index, count) => Ok(Some(Shard { index, count })),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}-of-{}", self.index, self.count)
+    }
+
+    pub fn owns(&self, site: &str, isin: &str) -> bool {
+        self.owns_key(&format!("{}/{}", site, isin.trim()))
+    }
+
+    // watchlist ISINs are split before they are routed, when their site is not known yet
+    pub fn owns_isin(&self, isin: &str) -> bool {
+        self.owns_key(isin.trim())
+    }
+
+    // stable across processes and releases, unlike the std hasher
+    fn owns_key(&self, key: &str) -> bool {
+        let digest = Sha256::digest(key.as_bytes());
+        let hash = u64::from_be_bytes(digest[..8].try_into().unwrap());
+        hash % self.count as u64 == self.index as u64
+    }
+}
+
+// shards of one observation: <output>/shards/<source>-<obsdatetime>/<index>-of-<count>.csv
+pub fn shard_dir(output_path_prefix: &str, site: &str, obsdatetime: &str) -> String {
+    let name = obs_file_name(site, obsdatetime, "csv");
+    let name = name.trim_end_matches(".csv");
+    [output_path_prefix, SHARDS_DIR, "/", name, "/"].concat()
+}
+
+pub fn shard_path(shard_dir: &str, shard: &Shard) -> String {
+    [shard_dir, &shard.name(), ".csv"].concat()
+}
+
+// header, rows and marker of one shard file, an empty shard has no header
+fn read_shard(path: &Path) -> Result<(Vec<String>, Vec<csv::StringRecord>, String), String> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .has_headers(false)
+        .from_path(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut header = Vec::new();
+    let mut rows = Vec::new();
+    let mut marker = PARTIAL_MARKER.to_string(); // a shard without marker was cut short
+    for record in rdr.records() {
+        let record = record.map_err(|e| format!("{}: {}", path.display(), e))?;
+        if record[0].starts_with("-- ") {
+            marker = record[0].to_string();
+        } else if header.is_empty() {
+            header = record.iter().map(|h| h.to_string()).collect();
+        } else {
+            rows.push(record);
+        }
+    }
+    Ok((header, rows, marker))
+}
+
+// held by the task merging an observation, so that two shards finishing together don't
+// both merge it; removed when dropped, after the merged file is committed
+pub struct MergeMarker {
+    shard_dir: String,
+    path: String,
+}
+
+impl MergeMarker {
+    // None when another task is merging; a marker left by a crashed merge is taken over
+    fn take(shard_dir: &str) -> Result<Option<MergeMarker>, String> {
+        let path = [shard_dir, MERGE_MARKER].concat();
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_file) => {
+                    return Ok(Some(MergeMarker {
+                        shard_dir: shard_dir.to_string(),
+                        path,
+                    }));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(&path).and_then(|metadata| metadata.modified()).is_ok_and(|modified| {
+                        modified.elapsed().unwrap_or_default() >= Duration::from_secs(MERGE_MARKER_TTL)
+                    });
+                    if !stale {
+                        return Ok(None);
+                    }
+                    log!("[MERGE] {} left by a crashed merge, taking over", path);
+                    // another task may have taken it over first, create_new decides
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e) => return Err(format!("{}: {}", path, e)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for MergeMarker {
+    fn drop(&mut self) {
+        // gone with the shard directory once the merge is done
+        if let Err(e) = fs::remove_file(&self.path)
+            && e.kind() != ErrorKind::NotFound
+        {
+            eprintln!("[MERGE] {}: {}", self.path, e);
+        }
+    }
+}
+
+pub struct Merged {
+    pub file: ClosedFile,
+    pub marker: String, // EMPTY_SHARD_MARKER when every shard is empty
+    merging: MergeMarker,
+}
+
+impl Merged {
+    // once the merged file is committed the shards are not needed anymore; removed while
+    // the marker is held, so that no other task starts merging them again
+    pub fn remove_shards(self) {
+        if let Err(e) = fs::remove_dir_all(&self.merging.shard_dir) {
+            eprintln!("[MERGE] {}: {}", self.merging.shard_dir, e);
+        }
+    }
+}
+
+// shard of a source none of this task's watchlist ISINs went to, the other shards may have some
+pub fn write_empty_shard(shard_dir: &str, shard: &Shard) -> Result<(), String> {
+    fs::create_dir_all(shard_dir).map_err(|e| format!("{}: {}", shard_dir, e))?;
+    let path = shard_path(shard_dir, shard);
+    write_atomic(&path, format!("{}\n", EMPTY_SHARD_MARKER).as_bytes()).map_err(|e| format!("{}: {}", path, e))
+}
+
+// once every shard of the observation is there, combine them into a closed file at
+// `output_filepath` (still under its temp name) with its marker, partial if any shard is
+// and empty if all are; None while shards are missing or another task is merging them
+pub fn merge_shards(shard_dir: &str, count: usize, output_filepath: &str) -> Result<Option<Merged>, String> {
+    let paths: Vec<String> = (0..count).map(|index| shard_path(shard_dir, &Shard { index, count })).collect();
+    if !paths.iter().all(|path| Path::new(path).exists()) {
+        return Ok(None);
+    }
+    let Some(merging) = MergeMarker::take(shard_dir)? else {
+        log!("[MERGE] {} is being merged by another task", shard_dir);
+        return Ok(None);
+    };
+    let tmp_path = temp_path(output_filepath);
+    let write_error = |e: String| format!("{}: {}", tmp_path, e);
+    let mut wtr = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(File::create(&tmp_path).map_err(|e| write_error(e.to_string()))?);
+    let mut partial = false;
+    let mut empty = true;
+    let mut rows = 0;
+    for path in &paths {
+        let (header, records, marker) = read_shard(Path::new(path))?;
+        if marker == EMPTY_SHARD_MARKER {
+            continue;
+        }
+        if empty {
+            wtr.write_record(&header).map_err(|e| write_error(e.to_string()))?;
+            empty = false;
+        }
+        for record in &records {
+            wtr.write_record(record).map_err(|e| write_error(e.to_string()))?;
+        }
+        rows += records.len();
+        partial |= marker == PARTIAL_MARKER;
+    }
+    let marker = match (empty, partial) {
+        (true, _) => EMPTY_SHARD_MARKER,
+        (false, true) => PARTIAL_MARKER,
+        (false, false) => END_MARKER,
+    };
+    wtr.write_record([marker]).map_err(|e| write_error(e.to_string()))?;
+    let file = wtr.into_inner().map_err(|e| write_error(e.to_string()))?;
+    file.sync_all().map_err(|e| write_error(e.to_string()))?;
+    Ok(Some(Merged {
+        file: ClosedFile {
+            tmp_path,
+            output_filepath: output_filepath.to_string(),
+            rows,
+        },
+        marker: marker.to_string(),
+        merging,
+    }))
+}
+
+// shard directory whose observation was never merged
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Unmerged {
+    pub site: String,
+    pub shard_dir: String,
+    pub count: usize,
+    pub output_filepath: String,
+}
+
+pub fn unmerged_shards(output_path_prefix: &str) -> Vec<Unmerged> {
+    let root = [output_path_prefix, SHARDS_DIR].concat();
+    let Ok(entries) = fs::read_dir(&root) else {
+        return Vec::new();
+    };
+    let mut pending = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let output_filepath = [output_path_prefix, &name, ".csv"].concat();
+        if Path::new(&output_filepath).exists() {
+            continue;
+        }
+        let Some(obs) = parse_file_name(&[name.as_str(), ".csv"].concat(), &utc_offset()) else {
+            continue;
+        };
+        // shard files are named <index>-of-<count>.csv
+        let count = fs::read_dir(entry.path())
+            .into_iter()
+            .flatten()
+            .filter_map(|shard| shard.ok())
+            .filter_map(|shard| {
+                let file_name = shard.file_name().to_string_lossy().to_string();
+                file_name.strip_suffix(".csv")?.split_once("-of-")?.1.parse::<usize>().ok()
+            })
+            .max();
+        if let Some(count) = count {
+            pending.push(Unmerged {
+                site: obs.source,
+                shard_dir: [root.as_str(), "/", &name, "/"].concat(),
+                count,
+                output_filepath,
+            });
+        }
+    }
+    pending.sort();
+    pending
+}
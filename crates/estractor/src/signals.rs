@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use tokio::signal::unix::{SignalKind, signal};
+use tokio_util::sync::CancellationToken;
+
+use crate::utils::log;
+
+// cancels the run on SIGTERM (Cloud Run timeout) or when the deadline expires
+pub fn watch_deadline(cancel: CancellationToken, deadline: Option<Duration>) {
+    let mut sigterm = signal(SignalKind::terminate()).expect("cannot listen for SIGTERM");
+    tokio::spawn(async move {
+        let deadline = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = deadline => log!("\n> Deadline reached, cancelling pending requests"),
+            _ = sigterm.recv() => log!("\n> SIGTERM received, cancelling pending requests"),
+            _ = cancel.cancelled() => return,
+        }
+        cancel.cancel();
+    });
+}
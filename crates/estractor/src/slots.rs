@@ -0,0 +1,59 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::definitions::globals::{SLOT_FILE_TTL, SLOTS_DIR};
+use crate::utils::log;
+
+const SLOT_READ_ATTEMPTS: usize = 10;
+
+// slot ids come from the environment, keep them usable as file names
+fn slot_file_name(slot: &str) -> String {
+    slot.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect()
+}
+
+// obsdatetime of a schedule slot: the first attempt records it, retries and the other
+// shards reuse it so that they write the same file names and replace that attempt's output
+pub async fn slot_obsdatetime(output_path_prefix: &str, slot: &str, obsdatetime: &str) -> Result<String, String> {
+    let dir = Path::new(output_path_prefix).join(SLOTS_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let path = dir.join(slot_file_name(slot));
+    match OpenOptions::new().write(true).create_new(true).open(&path) {
+        Ok(mut file) => {
+            file.write_all(format!("{}\n", obsdatetime).as_bytes())
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            log!("[SLOT] {} starts at {}", slot, obsdatetime);
+            remove_old_slots(&dir);
+            Ok(obsdatetime.to_string())
+        }
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            // the parallel task that created it may still be writing it
+            for _ in 0..SLOT_READ_ATTEMPTS {
+                let recorded = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let recorded = recorded.trim();
+                if !recorded.is_empty() {
+                    log!("[SLOT] {} already started, reusing {}", slot, recorded);
+                    return Ok(recorded.to_string());
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            Err(format!("{}: empty slot file", path.display()))
+        }
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+// one file per slot ever run, removed once no retry of theirs can come anymore
+fn remove_old_slots(dir: &Path) {
+    for entry in fs::read_dir(dir).into_iter().flatten().filter_map(|entry| entry.ok()) {
+        let old = entry.metadata().and_then(|metadata| metadata.modified()).is_ok_and(|modified| {
+            modified.elapsed().unwrap_or_default() >= Duration::from_secs(SLOT_FILE_TTL)
+        });
+        if old && fs::remove_file(entry.path()).is_ok() {
+            log!("[SLOT] removed {}", entry.path().display());
+        }
+    }
+}
@@ -0,0 +1,681 @@
+use std::borrow::Cow;
+
+use regex::Regex;
+
+use crate::definitions::types::{Extraction, ExtractionRule};
+use crate::extractors::{get_ask_price_pattern, get_ask_price_selector, pattern_extraction, strategy};
+use crate::utils::price_formatter;
+
+// tail kept between chunks so that a pattern cut by a chunk boundary still matches
+const PATTERN_OVERLAP: usize = 1024;
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+// their content is text up to the end tag, a "<" in a script is not a tag
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+// the parser closes these without an end tag (at the next <td>, <li>, a block in a <p>...),
+// a match on one of them goes through the DOM
+const OPTIONAL_END_ELEMENTS: [&str; 21] = [
+    "body", "caption", "colgroup", "dd", "dt", "head", "html", "li", "optgroup", "option", "p", "rb", "rp", "rt",
+    "rtc", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+// start tags that close an open <p> (a <table> only outside quirks mode)
+const P_CLOSING_ELEMENTS: [&str; 37] = [
+    "address", "article", "aside", "blockquote", "center", "dd", "details", "dialog", "dir", "div", "dl", "dt",
+    "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hgroup",
+    "hr", "li", "main", "menu", "nav", "ol", "p", "pre", "section", "summary", "table", "ul",
+];
+
+struct Tag {
+    name: String, // lowercase
+    attrs: Vec<(String, String)>,
+    end: bool,
+    self_closing: bool,
+}
+
+fn attr<'a>(tag: &'a Tag, name: &str) -> Option<&'a str> {
+    tag.attrs.iter().find(|(n, _v)| n == name).map(|(_n, v)| v.as_str())
+}
+
+// compound selector without combinators: tag, #id, .class, [attr] and [attr="value"]
+#[derive(Debug, Default)]
+struct SimpleSelector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+fn name_len(s: &str) -> usize {
+    s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_')).unwrap_or(s.len())
+}
+
+impl SimpleSelector {
+    // None for selectors the scanner cannot match, those pages go through the DOM
+    fn parse(rule: &str) -> Option<SimpleSelector> {
+        let mut selector = SimpleSelector::default();
+        let mut rest = rule.trim();
+        let n = name_len(rest);
+        if n > 0 {
+            selector.tag = Some(rest[..n].to_ascii_lowercase());
+            rest = &rest[n..];
+        }
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '#' | '.' => {
+                    let n = name_len(&rest[1..]);
+                    if n == 0 {
+                        return None;
+                    }
+                    let name = rest[1..1 + n].to_string();
+                    match c {
+                        '#' => selector.id = Some(name),
+                        _ => selector.classes.push(name),
+                    }
+                    rest = &rest[1 + n..];
+                }
+                '[' => {
+                    let end = rest.find(']')?;
+                    let (name, value) = match rest[1..end].split_once('=') {
+                        Some((name, value)) => (name.trim(), Some(value.trim().trim_matches(['"', '\'']).to_string())),
+                        None => (rest[1..end].trim(), None),
+                    };
+                    // [attr~=v], [attr^=v] and the like
+                    if name.is_empty() || name_len(name) != name.len() {
+                        return None;
+                    }
+                    selector.attrs.push((name.to_ascii_lowercase(), value));
+                    rest = &rest[end + 1..];
+                }
+                // combinators and pseudo-classes
+                _ => return None,
+            }
+        }
+        let empty = selector.tag.is_none() && selector.id.is_none() && selector.classes.is_empty() && selector.attrs.is_empty();
+        if empty { None } else { Some(selector) }
+    }
+
+    fn matches(&self, tag: &Tag) -> bool {
+        self.tag.as_ref().is_none_or(|name| *name == tag.name)
+            && self.id.as_deref().is_none_or(|id| attr(tag, "id") == Some(id))
+            && self.classes.iter().all(|class| {
+                attr(tag, "class").is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+            })
+            && self.attrs.iter().all(|(name, value)| match (attr(tag, name), value) {
+                (Some(found), Some(value)) => found == value,
+                (found, None) => found.is_some(),
+                (None, Some(_value)) => false,
+            })
+    }
+}
+
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        "euro" => Some('€'),
+        _ => {
+            let code = match name.strip_prefix("#x").or(name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+// the entities price pages use, others are left as written
+fn decode_entities(s: &str) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = rest.find(';').filter(|end| *end <= 10);
+        match end.and_then(|end| Some((entity(&rest[1..end])?, end))) {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+// "<span data-field="ask" class='x'>" -> span, [(data-field, ask), (class, x)]
+fn parse_tag(source: &str) -> Tag {
+    let end = source.starts_with("</");
+    let inner = &source[if end { 2 } else { 1 }..source.len() - 1];
+    let self_closing = inner.ends_with('/');
+    let inner = inner.trim_end_matches('/');
+    let n = inner.find(|c: char| c.is_ascii_whitespace() || c == '/').unwrap_or(inner.len());
+    let name = inner[..n].to_ascii_lowercase();
+    let mut attrs = Vec::new();
+    let mut rest = &inner[n..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let n = rest.find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/').unwrap_or(rest.len());
+        let attr_name = rest[..n].to_ascii_lowercase();
+        rest = rest[n..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                let after = after.trim_start();
+                match after.chars().next() {
+                    Some(quote) if quote == '"' || quote == '\'' => {
+                        let close = after[1..].find(quote).map(|i| i + 1).unwrap_or(after.len());
+                        rest = after.get(close + 1..).unwrap_or("");
+                        &after[1..close]
+                    }
+                    _ => {
+                        let close = after.find(|c: char| c.is_ascii_whitespace()).unwrap_or(after.len());
+                        rest = &after[close..];
+                        &after[..close]
+                    }
+                }
+            }
+            None => "",
+        };
+        attrs.push((attr_name, decode_entities(value).into_owned()));
+    }
+    Tag { name, attrs, end, self_closing }
+}
+
+enum Markup {
+    Incomplete, // cut by the chunk boundary
+    Text,       // a "<" that starts no markup
+    Other(usize),
+    Tag(usize),
+}
+
+// "<" followed by a tag, comment, doctype or text, with the length of the markup
+fn markup(rest: &str) -> Markup {
+    let tag_len = |rest: &str| {
+        let mut quote = None;
+        for (i, c) in rest.char_indices().skip(1) {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_q) => {}
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if c == '>' => return Markup::Tag(i + 1),
+                None => {}
+            }
+        }
+        Markup::Incomplete
+    };
+    let other_len = |rest: &str, close: &str| match rest.find(close) {
+        Some(i) => Markup::Other(i + close.len()),
+        None => Markup::Incomplete,
+    };
+    let bytes = rest.as_bytes();
+    match bytes.get(1) {
+        None => Markup::Incomplete,
+        Some(b'!') if rest.starts_with("<!--") => other_len(&rest[4..], "-->").shifted(4),
+        Some(b'!') | Some(b'?') => other_len(rest, ">"),
+        Some(b'/') => match bytes.get(2) {
+            None => Markup::Incomplete,
+            Some(c) if c.is_ascii_alphabetic() => tag_len(rest),
+            Some(_c) => other_len(rest, ">"),
+        },
+        Some(c) if c.is_ascii_alphabetic() => tag_len(rest),
+        Some(_c) => Markup::Text,
+    }
+}
+
+impl Markup {
+    fn shifted(self, by: usize) -> Markup {
+        match self {
+            Markup::Other(len) => Markup::Other(len + by),
+            markup => markup,
+        }
+    }
+}
+
+// element matched so far, until its end tag
+struct Capture {
+    open: Vec<String>, // the element and the ones opened inside it, innermost last
+    matched: String,
+    text: String,
+}
+
+struct SelectorScan {
+    selector: SimpleSelector,
+    strategy: String,
+    attribute: Option<String>, // read instead of the text
+    pending: String,           // input not scanned yet, e.g. a tag cut by the chunk boundary
+    raw_text: Option<String>,  // inside a script or style element
+    p_open: bool,              // a <p> may be open around the match
+    capture: Option<Capture>,
+    found: Option<Result<Extraction, &'static str>>,
+}
+
+impl SelectorScan {
+    fn extraction(&self, capture: Capture) -> Extraction {
+        let text = capture.text.trim().to_string();
+        Extraction {
+            matched: capture.matched,
+            value: price_formatter(&text),
+            raw: text,
+            strategy: self.strategy.clone(),
+            currency: None,
+            bid: None,
+        }
+    }
+
+    fn attribute_extraction(&self, source: &str, value: &str) -> Extraction {
+        Extraction {
+            matched: source.to_string(),
+            value: price_formatter(value),
+            raw: value.trim().to_string(),
+            strategy: self.strategy.clone(),
+            currency: None,
+            bid: None,
+        }
+    }
+
+    fn text(&mut self, source: &str) {
+        if let Some(capture) = &mut self.capture {
+            capture.matched.push_str(source);
+            capture.text.push_str(&decode_entities(source));
+        }
+    }
+
+    // script or style content, entities are not decoded there
+    fn raw(&mut self, source: &str) {
+        if let Some(capture) = &mut self.capture {
+            capture.matched.push_str(source);
+            capture.text.push_str(source);
+        }
+    }
+
+    fn tag(&mut self, tag: Tag, source: &str) {
+        if !tag.end && !tag.self_closing && RAW_TEXT_ELEMENTS.contains(&tag.name.as_str()) {
+            self.raw_text = Some(tag.name.clone());
+        }
+        if let Some(capture) = &mut self.capture {
+            capture.matched.push_str(source);
+            match tag.end {
+                // an end tag of an element opened outside closes the match implicitly in the DOM
+                true => match capture.open.iter().rposition(|name| *name == tag.name) {
+                    Some(0) => {
+                        let capture = self.capture.take().unwrap();
+                        self.found = Some(Ok(self.extraction(capture)));
+                    }
+                    Some(i) => capture.open.truncate(i),
+                    None => self.found = Some(Err("element closed implicitly")),
+                },
+                // a new cell, item or paragraph, or a block in an open <p>, ends the elements around it
+                false if OPTIONAL_END_ELEMENTS.contains(&tag.name.as_str())
+                    || self.p_open && P_CLOSING_ELEMENTS.contains(&tag.name.as_str()) =>
+                {
+                    self.found = Some(Err("element closed implicitly"));
+                }
+                false if !tag.self_closing && !VOID_ELEMENTS.contains(&tag.name.as_str()) => capture.open.push(tag.name),
+                false => {}
+            }
+            return;
+        }
+        // on the safe side: a <p> closed by the end tag of its parent still counts as open
+        match (tag.end, tag.name.as_str()) {
+            (false, "p") => self.p_open = true,
+            (true, "p") => self.p_open = false,
+            (false, "table") => {}
+            (false, name) if P_CLOSING_ELEMENTS.contains(&name) => self.p_open = false,
+            _ => {}
+        }
+        if !tag.end && self.selector.matches(&tag) && self.attribute.is_some() {
+            // the first element decides, as in the DOM extractor
+            let value = self.attribute.as_deref().and_then(|name| attr(&tag, name));
+            self.found = Some(value.map(|value| self.attribute_extraction(source, value)).ok_or("attribute not found"));
+        } else if !tag.end && self.selector.matches(&tag) {
+            let capture = Capture {
+                matched: source.to_string(),
+                text: String::new(),
+                open: vec![tag.name.clone()],
+            };
+            if OPTIONAL_END_ELEMENTS.contains(&tag.name.as_str()) {
+                self.found = Some(Err("element with an optional end tag"));
+            } else if tag.self_closing || VOID_ELEMENTS.contains(&tag.name.as_str()) {
+                self.found = Some(Ok(self.extraction(capture)));
+            } else {
+                self.capture = Some(capture);
+            }
+        }
+    }
+
+    // scans `input` up to the match or the first markup cut by the chunk boundary,
+    // returns the bytes consumed
+    fn scan(&mut self, input: &str, eof: bool) -> usize {
+        let mut pos = 0;
+        while self.found.is_none() {
+            let rest = &input[pos..];
+            if let Some(raw_text) = &self.raw_text {
+                let close = format!("</{}", raw_text);
+                match rest.to_ascii_lowercase().find(&close) {
+                    Some(i) => {
+                        self.raw(&rest[..i]);
+                        pos += i;
+                        self.raw_text = None;
+                        continue;
+                    }
+                    None => {
+                        // keep what could be the start of the end tag
+                        let keep = if eof { 0 } else { close.len() };
+                        let cut = rest.floor_char_boundary(rest.len().saturating_sub(keep));
+                        self.raw(&rest[..cut]);
+                        pos += cut;
+                        break;
+                    }
+                }
+            }
+            match rest.find('<') {
+                None => {
+                    // an entity cut by the chunk boundary waits for the next chunk
+                    let cut = match rest.rfind('&') {
+                        Some(i) if !eof && !rest[i..].contains(';') && rest.len() - i < 12 => i,
+                        _ => rest.len(),
+                    };
+                    self.text(&rest[..cut]);
+                    pos += cut;
+                    break;
+                }
+                Some(i) => {
+                    self.text(&rest[..i]);
+                    pos += i;
+                    let rest = &input[pos..];
+                    match markup(rest) {
+                        Markup::Incomplete if eof => {
+                            self.text(rest);
+                            pos = input.len();
+                            break;
+                        }
+                        Markup::Incomplete => break,
+                        Markup::Text => {
+                            self.text("<");
+                            pos += 1;
+                        }
+                        Markup::Other(len) => pos += len,
+                        Markup::Tag(len) => {
+                            self.tag(parse_tag(&rest[..len]), &rest[..len]);
+                            pos += len;
+                        }
+                    }
+                }
+            }
+        }
+        pos
+    }
+
+    // true once the price is found; after a failure the whole page is needed by the DOM extractor
+    fn feed(&mut self, chunk: &str) -> bool {
+        if self.found.is_none() {
+            self.pending.push_str(chunk);
+            let pending = std::mem::take(&mut self.pending);
+            let consumed = self.scan(&pending, false);
+            self.pending = pending[consumed..].to_string();
+        }
+        matches!(self.found, Some(Ok(_)))
+    }
+
+    fn finish(mut self) -> Result<Extraction, &'static str> {
+        if self.found.is_none() {
+            let pending = std::mem::take(&mut self.pending);
+            self.scan(&pending, true);
+        }
+        if let Some(found) = self.found {
+            return found;
+        }
+        // an element left open at the end of the page may have been closed implicitly before
+        match self.capture {
+            Some(_capture) => Err("element not closed"),
+            None => Err("no element matched"),
+        }
+    }
+}
+
+struct PatternScan {
+    re: Regex,
+    rule: String,
+    buffer: String,
+    found: Option<Extraction>,
+}
+
+impl PatternScan {
+    fn feed(&mut self, chunk: &str) -> bool {
+        self.buffer.push_str(chunk);
+        let captures = self.re.captures(&self.buffer);
+        let mat = captures.as_ref().and_then(|captures| captures.get(0));
+        // a match touching the end may still grow with the next chunk
+        if let (Some(captures), Some(mat)) = (&captures, mat)
+            && mat.end() < self.buffer.len()
+        {
+            self.found = Some(pattern_extraction(captures, &self.rule));
+            return true;
+        }
+        let keep_from = match mat {
+            Some(mat) => mat.start(),
+            None => self.buffer.len().saturating_sub(PATTERN_OVERLAP),
+        };
+        let cut = self.buffer.floor_char_boundary(keep_from);
+        self.buffer.drain(..cut);
+        false
+    }
+
+    fn finish(self) -> Result<Extraction, &'static str> {
+        if let Some(found) = self.found {
+            return Ok(found);
+        }
+        let captures = self.re.captures(&self.buffer).ok_or("no pattern matched")?;
+        Ok(pattern_extraction(&captures, &self.rule))
+    }
+}
+
+// extraction over the page as it arrives, without building a DOM: feed it chunk by chunk,
+// the rest of the page is not downloaded once `feed` is true (the part read is still kept
+// by the caller for the block checks)
+pub struct StreamScan(Scan);
+
+enum Scan {
+    Selector(Box<SelectorScan>),
+    Pattern(Box<PatternScan>),
+}
+
+impl StreamScan {
+    // None when the rule of the site cannot be streamed, the page then goes through `extract`
+    pub fn new(extractor: &str, source_site: &str, rule: &ExtractionRule) -> Option<StreamScan> {
+        match extractor.trim() {
+            "selector" => {
+                let selector_rule = match &rule.rule {
+                    Some(selector_rule) => selector_rule.as_str(),
+                    None => get_ask_price_selector(source_site).ok()?,
+                };
+                Some(StreamScan(Scan::Selector(Box::new(SelectorScan {
+                    selector: SimpleSelector::parse(selector_rule)?,
+                    strategy: strategy(selector_rule, rule.attribute.as_deref()),
+                    attribute: rule.attribute.clone(),
+                    pending: String::new(),
+                    raw_text: None,
+                    p_open: false,
+                    capture: None,
+                    found: None,
+                }))))
+            }
+            "pattern" => {
+                let pattern_rule = match &rule.rule {
+                    Some(pattern_rule) => pattern_rule.as_str(),
+                    None => get_ask_price_pattern(source_site).ok()?,
+                };
+                Some(StreamScan(Scan::Pattern(Box::new(PatternScan {
+                    re: Regex::new(pattern_rule).ok()?,
+                    rule: pattern_rule.to_string(),
+                    buffer: String::new(),
+                    found: None,
+                }))))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &str) -> bool {
+        match &mut self.0 {
+            Scan::Selector(scan) => scan.feed(chunk),
+            Scan::Pattern(scan) => scan.feed(chunk),
+        }
+    }
+
+    pub fn finish(self) -> Result<Extraction, &'static str> {
+        match self.0 {
+            Scan::Selector(scan) => scan.finish(),
+            Scan::Pattern(scan) => scan.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractors::extract;
+
+    // `html` in pieces of `size` bytes, on character boundaries
+    fn chunks(html: &str, size: usize) -> Vec<&str> {
+        let mut chunks = Vec::new();
+        let mut rest = html;
+        while !rest.is_empty() {
+            let end = rest.ceil_char_boundary(size.min(rest.len()));
+            chunks.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+        chunks
+    }
+
+    fn streamed(extractor: &str, html: &str, rule: &ExtractionRule, size: usize) -> Result<(String, String), &'static str> {
+        let mut scan = StreamScan::new(extractor, "marex", rule).expect("rule can be streamed");
+        for chunk in chunks(html, size) {
+            if scan.feed(chunk) {
+                break;
+            }
+        }
+        scan.finish().map(|extraction| (extraction.value, extraction.raw))
+    }
+
+    // the streamed extraction agrees with the DOM one whatever the chunk boundaries
+    fn assert_same(extractor: &str, html: &str, rule: &ExtractionRule) {
+        let dom = extract(extractor, html, "marex", rule).map(|extraction| (extraction.value, extraction.raw));
+        for size in 1..=html.len() {
+            assert_eq!(streamed(extractor, html, rule, size), dom, "chunks of {} bytes", size);
+        }
+    }
+
+    // pages the scan leaves to the DOM extractor: the fetcher writes what the DOM finds
+    fn assert_dom_fallback(html: &str) {
+        let rule = ExtractionRule::default();
+        assert!(extract("selector", html, "marex", &rule).is_ok());
+        for size in 1..=html.len() {
+            assert!(streamed("selector", html, &rule, size).is_err(), "chunks of {} bytes", size);
+        }
+    }
+
+    fn selector(rule: &str) -> ExtractionRule {
+        ExtractionRule {
+            rule: Some(rule.to_string()),
+            ..ExtractionRule::default()
+        }
+    }
+
+    #[test]
+    fn builtin_selector() {
+        let html = r#"<html><body><div id="product-ask-price"> 101,50 </div></body></html>"#;
+        assert_same("selector", html, &ExtractionRule::default());
+    }
+
+    #[test]
+    fn tags_cut_by_chunks() {
+        let html = r#"<p class="a b">x</p><div data-note='a > b' class="quote ask" id="product-ask-price"
+            >1.234,50</div><div class="ask">9</div>"#;
+        assert_same("selector", html, &ExtractionRule::default());
+        assert_same("selector", html, &selector("div.quote.ask"));
+        assert_same("selector", html, &selector(r#"[data-note="a > b"]"#));
+    }
+
+    #[test]
+    fn entities() {
+        let html = "<div id=\"product-ask-price\">&nbsp;1&#46;234&#x2C;50&nbsp;&euro; &amp; &unknown;</div>";
+        assert_same("selector", html, &ExtractionRule::default());
+        let html = r#"<span data-field="ask" data-value="1&#44;25">x</span>"#;
+        let rule = ExtractionRule {
+            rule: Some(r#"span[data-field="ask"]"#.to_string()),
+            attribute: Some("data-value".to_string()),
+            script: None,
+        };
+        assert_same("selector", html, &rule);
+    }
+
+    #[test]
+    fn script_raw_text() {
+        let html = r#"<head><script>if (a<b) document.write("<div id='product-ask-price'>9,99</div>");</script>
+            <style>p > b { color: red }</style></head><!-- <div id="product-ask-price">0</div> -->
+            <div id="product-ask-price">1,50</div>"#;
+        assert_same("selector", html, &ExtractionRule::default());
+        let html = r#"<div id="product-ask-price">1,<script>var x = "&amp;<b>";</script>50</div>"#;
+        assert_same("selector", html, &ExtractionRule::default());
+    }
+
+    #[test]
+    fn nested_same_name() {
+        let html = r#"<div><div id="product-ask-price"><div>1</div>.<div><div>2</div>34</div>,5<br>0</div>7</div>"#;
+        assert_same("selector", html, &ExtractionRule::default());
+    }
+
+    #[test]
+    fn optional_end_tags() {
+        assert_dom_fallback(r#"<table><tr><td id="product-ask-price">1,50<td>2,00</tr><tr><td>999</table><p>footer 7"#);
+        assert_dom_fallback(r#"<p id="product-ask-price">1,50<div>other 9</div><p>x"#);
+        assert_dom_fallback(r#"<ul><li><span id="product-ask-price">1,50<li>2,00</ul><b>9</b>"#);
+        assert_dom_fallback(r#"<table><tr><td><div id="product-ask-price">1,50</td></tr></table>3"#);
+        assert_dom_fallback(r#"<p>Lettera <span id="product-ask-price">1,50<div>other 9</div></span></p>"#);
+        assert_dom_fallback(r#"<dl><dt>Lettera<dd><b id="product-ask-price">1,50<dt>Denaro<dd>1,40</dl>"#);
+        // closed explicitly the scan still agrees with the DOM
+        let html = r#"<table><tr><td><span id="product-ask-price">1,50</span></td><td>2,00</td></tr></table>"#;
+        assert_same("selector", html, &ExtractionRule::default());
+        let html = r#"<p>Lettera <span id="product-ask-price">1,50</span></p><p>9"#;
+        assert_same("selector", html, &ExtractionRule::default());
+        let html = r#"<p>Lettera</p><div id="product-ask-price"><div>1,50</div></div><p>9"#;
+        assert_same("selector", html, &ExtractionRule::default());
+    }
+
+    // the scan gives way to the DOM extractor instead of guessing
+    #[test]
+    fn unclosed_falls_back() {
+        for html in [r#"<td id="product-ask-price">1,50<td>2,00"#, r#"<div id="product-ask-price">1,50"#] {
+            let mut scan = StreamScan::new("selector", "marex", &ExtractionRule::default()).unwrap();
+            assert!(!scan.feed(html));
+            assert!(scan.finish().is_err());
+        }
+    }
+
+    #[test]
+    fn no_match() {
+        assert_same("selector", "<div id=\"other\">1,50</div><div", &ExtractionRule::default());
+    }
+
+    #[test]
+    fn pattern_cut_by_chunks() {
+        let html = r#"<script>var state = {"bid":1.2,"ask":101.55,"currency":"EUR"};</script>"#;
+        let rule = selector(r#""ask":([0-9]+\.?[0-9]*),"#);
+        assert_same("pattern", html, &rule);
+    }
+}
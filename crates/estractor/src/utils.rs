@@ -1,3 +1,28 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// set when stdout carries data (quote command), progress messages then go to stderr
+static STDOUT_IS_DATA: AtomicBool = AtomicBool::new(false);
+
+pub fn reserve_stdout() {
+    STDOUT_IS_DATA.store(true, Ordering::Relaxed);
+}
+
+pub fn stdout_is_data() -> bool {
+    STDOUT_IS_DATA.load(Ordering::Relaxed)
+}
+
+// println! that keeps out of the way when stdout is reserved for data
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if $crate::utils::stdout_is_data() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use log;
+
 pub fn price_formatter(price: &str) -> String {
     let mut p = price.trim().to_string();
     if p.contains(",") && p.contains(".") {
@@ -5,4 +30,4 @@ pub fn price_formatter(price: &str) -> String {
     }
     p = p.replace(",", ".");
     p
-}
\ No newline at end of file
+}
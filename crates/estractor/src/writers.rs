@@ -0,0 +1,278 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use common::markers::PARTIAL_MARKER;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::definitions::globals::QUOTE_CHANNEL_SIZE;
+use crate::definitions::types::Quote;
+use crate::utils::log;
+
+pub const CSV_HEADER: [&str; 5] = ["isin", "name", "ask", "bid", "currency"];
+pub const PROVENANCE_HEADER: [&str; 7] = ["fetched_at", "url", "http_status", "extractor", "strategy", "run_id", "not_modified"];
+
+// optional CSV columns after the quote itself
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Columns {
+    pub provenance: bool,
+    pub session: bool,
+}
+
+impl Columns {
+    fn header(&self) -> Vec<&'static str> {
+        let mut header = CSV_HEADER.to_vec();
+        if self.provenance {
+            header.extend(PROVENANCE_HEADER);
+        }
+        if self.session {
+            header.push("session");
+        }
+        header
+    }
+
+    fn record(&self, quote: &Quote) -> Vec<String> {
+        let mut record = vec![
+            quote.isin.clone(),
+            quote.name.clone(),
+            quote.ask.clone(),
+            quote.bid.clone(),
+            quote.currency.clone(),
+        ];
+        if self.provenance {
+            let p = quote.provenance.clone().unwrap_or_default();
+            record.extend([p.fetched_at, p.url, p.http_status.to_string(), p.extractor, p.strategy, p.run_id]);
+            record.push(quote.not_modified.to_string());
+        }
+        if self.session {
+            record.push(quote.session.clone().unwrap_or_default());
+        }
+        record
+    }
+}
+
+// writes quotes one by one as CSV (with header) or JSON Lines
+pub enum QuoteWriter<W: Write> {
+    Csv(Box<csv::Writer<W>>, Columns),
+    Jsonl(W),
+}
+
+impl<W: Write> QuoteWriter<W> {
+    pub fn new(wtr: W, format: &str, columns: Columns) -> Result<Self, Box<dyn Error>> {
+        match format.trim() {
+            "csv" => {
+                // flexible so that the one-column marker line is accepted
+                let mut wtr = csv::WriterBuilder::new().flexible(true).from_writer(wtr);
+                wtr.write_record(columns.header())?;
+                Ok(QuoteWriter::Csv(Box::new(wtr), columns))
+            }
+            "jsonl" => Ok(QuoteWriter::Jsonl(wtr)),
+            _ => Err(format!("unknown output format: {}", format).into()),
+        }
+    }
+
+    pub fn write(&mut self, quote: &Quote) -> Result<(), Box<dyn Error>> {
+        match self {
+            QuoteWriter::Csv(wtr, columns) => wtr.write_record(columns.record(quote))?,
+            QuoteWriter::Jsonl(wtr) => {
+                serde_json::to_writer(&mut *wtr, quote)?;
+                wtr.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    // closing line such as the partial marker, outside the CSV records
+    pub fn write_marker(&mut self, marker: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            QuoteWriter::Csv(wtr, _) => wtr.write_record([marker])?,
+            QuoteWriter::Jsonl(wtr) => {
+                serde_json::to_writer(&mut *wtr, &serde_json::json!({ "marker": marker }))?;
+                wtr.write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        match self {
+            QuoteWriter::Csv(wtr, _) => wtr.flush()?,
+            QuoteWriter::Jsonl(wtr) => wtr.flush()?,
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> Result<W, Box<dyn Error>> {
+        match self {
+            QuoteWriter::Csv(wtr, _) => Ok(wtr.into_inner().map_err(|e| e.to_string())?),
+            QuoteWriter::Jsonl(mut wtr) => {
+                wtr.flush()?;
+                Ok(wtr)
+            }
+        }
+    }
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// hidden temp name in the same directory, readers only ever see complete files;
+// unique per process and call, so that parallel tasks writing the same file never share it
+pub fn temp_path(output_filepath: &str) -> String {
+    let path = Path::new(output_filepath);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let unique = format!("{}-{}", std::process::id(), TEMP_COUNTER.fetch_add(1, Ordering::Relaxed));
+    path.with_file_name(format!(".{}.{}.tmp", name, unique)).to_string_lossy().to_string()
+}
+
+// final name of a temp file: .<name>.<pid>-<n>.tmp, or .<name>.tmp from older releases
+fn temp_target(tmp_name: &str) -> Option<&str> {
+    let name = tmp_name.strip_prefix('.')?.strip_suffix(".tmp")?;
+    let unique = |part: &str| {
+        part.split_once('-')
+            .is_some_and(|(pid, n)| [pid, n].iter().all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit())))
+    };
+    match name.rsplit_once('.') {
+        Some((target, part)) if unique(part) => Some(target),
+        _ => Some(name),
+    }
+}
+
+// write to the temp name then rename over the final one
+pub fn write_atomic(output_filepath: &str, content: &[u8]) -> Result<(), std::io::Error> {
+    let tmp_path = temp_path(output_filepath);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, output_filepath)
+}
+
+// quote file left under its temp name by a crash: cut to its last complete line and closed
+// as partial, so the rows quoted before the crash are kept
+fn close_orphan(content: &str) -> String {
+    let mut content = match content.rfind('\n') {
+        Some(end) => content[..=end].to_string(),
+        None => return String::new(),
+    };
+    let last_line = content.trim_end().rsplit('\n').next().unwrap_or_default();
+    if !last_line.starts_with("-- ") {
+        content.push_str(PARTIAL_MARKER);
+        content.push('\n');
+    }
+    content
+}
+
+// temp files of an earlier run that crashed before committing them; only the ones `owns`
+// accepts and untouched for `min_age` count, live writers flush on every quote.
+// A quote file is kept under its final name unless a retry already wrote it, the rest is removed
+pub fn recover_temp_files(dir: &str, owns: impl Fn(&str) -> bool, min_age: Duration) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let tmp_name = entry.file_name().to_string_lossy().to_string();
+        let Some(name) = temp_target(&tmp_name) else {
+            continue;
+        };
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified.elapsed().unwrap_or_default() >= min_age);
+        if !owns(name) || !stale {
+            continue;
+        }
+        let tmp_path = entry.path();
+        let output_filepath = tmp_path.with_file_name(name);
+        let content = match name.ends_with(".csv") && !output_filepath.exists() {
+            true => fs::read_to_string(&tmp_path).map(|content| close_orphan(&content)).unwrap_or_default(),
+            false => String::new(),
+        };
+        let recovered = match content.is_empty() {
+            true => fs::remove_file(&tmp_path).map(|()| "removed"),
+            false => fs::write(&tmp_path, content)
+                .and_then(|()| fs::rename(&tmp_path, &output_filepath))
+                .map(|()| "kept as partial"),
+        };
+        match recovered {
+            Ok(outcome) => log!("[RECOVER] {} {}", tmp_path.display(), outcome),
+            Err(e) => eprintln!("[RECOVER] {}: {}", tmp_path.display(), e),
+        }
+    }
+}
+
+// output file fed by a channel, each quote is appended and flushed as it arrives
+pub struct StreamedFile {
+    wtr: QuoteWriter<File>,
+    tmp_path: String,
+    output_filepath: String,
+    pub rows: usize,
+}
+
+impl StreamedFile {
+    // closing marker, then fsync, the file keeps its temp name until committed
+    pub fn close(mut self, marker: &str) -> Result<ClosedFile, Box<dyn Error>> {
+        self.wtr.write_marker(marker)?;
+        let file = self.wtr.into_inner()?;
+        file.sync_all()?;
+        Ok(ClosedFile {
+            tmp_path: self.tmp_path,
+            output_filepath: self.output_filepath,
+            rows: self.rows,
+        })
+    }
+}
+
+pub struct ClosedFile {
+    pub tmp_path: String,
+    pub output_filepath: String,
+    pub rows: usize,
+}
+
+impl ClosedFile {
+    // rename to the final name
+    pub fn commit(&self) -> Result<(), std::io::Error> {
+        fs::rename(&self.tmp_path, &self.output_filepath)
+    }
+
+    pub fn discard(&self) -> Result<(), std::io::Error> {
+        fs::remove_file(&self.tmp_path)
+    }
+
+    // commit `content` in place of what was written
+    pub fn replace(&self, content: &str) -> Result<(), std::io::Error> {
+        self.discard()?;
+        write_atomic(&self.output_filepath, content.as_bytes())
+    }
+}
+
+pub type WriterTask = JoinHandle<Result<StreamedFile, String>>;
+
+pub fn spawn_quote_writer(
+    output_filepath: &str,
+    columns: Columns,
+) -> Result<(mpsc::Sender<Quote>, WriterTask), Box<dyn Error>> {
+    let tmp_path = temp_path(output_filepath);
+    let output_filepath = output_filepath.to_string();
+    let mut wtr = QuoteWriter::new(File::create(&tmp_path)?, "csv", columns)?;
+    wtr.flush()?;
+    let (tx, mut rx) = mpsc::channel::<Quote>(QUOTE_CHANNEL_SIZE);
+    let writer = tokio::task::spawn_blocking(move || {
+        let mut rows = 0;
+        while let Some(quote) = rx.blocking_recv() {
+            wtr.write(&quote).map_err(|e| e.to_string())?;
+            wtr.flush().map_err(|e| e.to_string())?;
+            rows += 1;
+        }
+        Ok(StreamedFile {
+            wtr,
+            tmp_path,
+            output_filepath,
+            rows,
+        })
+    });
+    Ok((tx, writer))
+}
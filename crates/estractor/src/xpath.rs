@@ -0,0 +1,361 @@
+use std::collections::{HashMap, HashSet};
+
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Html, Node};
+
+// the part of XPath 1.0 price rules need: /, //, name tests and *, predicates
+// ([1], [last()], [@a], [td], [@a='v'], [@a!='v'], [text()='v'], [td='v'], [contains(@a,'v')],
+// [starts-with(.,'v')], joined with "and"), and a final @attr or text() step
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Attr(String),
+    Text,          // text() and .
+    Child(String), // the child elements of that name, any of them can match
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    Position(usize),
+    Last,
+    Exists(Operand),
+    Equals(Operand, String),
+    NotEquals(Operand, String),
+    Contains(Operand, String),
+    StartsWith(Operand, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    descendant: bool,     // after //
+    name: Option<String>, // None for *
+    predicates: Vec<Vec<Predicate>>, // each [..], its conditions joined with "and"
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Select {
+    Element,
+    Attr(String),
+    Text,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XPath {
+    steps: Vec<Step>,
+    select: Select,
+}
+
+// "a/b[@c='/']" -> ["a", "b[@c='/']"] with the number of slashes before each part
+fn split_steps(expr: &str) -> Result<Vec<(usize, &str)>, String> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quote, mut start, mut slashes) = (0, None, 0, 0);
+    let bytes = expr.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_q) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '/' if depth == 0 => {
+                    if i > start {
+                        parts.push((slashes, &expr[start..i]));
+                        slashes = 0;
+                    }
+                    slashes += 1;
+                    if slashes > 2 {
+                        return Err(format!("unexpected /// in {}", expr));
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    if quote.is_some() || depth != 0 {
+        return Err(format!("unbalanced quotes or brackets in {}", expr));
+    }
+    if start >= expr.len() {
+        return Err(format!("{} ends with /", expr));
+    }
+    parts.push((slashes, &expr[start..]));
+    Ok(parts)
+}
+
+fn literal(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    let quoted = s.len() >= 2 && (s.starts_with('\'') && s.ends_with('\'') || s.starts_with('"') && s.ends_with('"'));
+    match quoted {
+        true => Ok(s[1..s.len() - 1].to_string()),
+        false => Err(format!("expected a quoted string, got {}", s)),
+    }
+}
+
+fn operand(s: &str) -> Result<Operand, String> {
+    match s.trim() {
+        "text()" | "." | "normalize-space()" | "normalize-space(.)" => Ok(Operand::Text),
+        s => match s.strip_prefix('@') {
+            Some(name) if !name.is_empty() => Ok(Operand::Attr(name.to_ascii_lowercase())),
+            Some(_name) => Err(format!("unsupported operand {}", s)),
+            None if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') => {
+                Ok(Operand::Child(s.to_ascii_lowercase()))
+            }
+            None => Err(format!("unsupported operand {}", s)),
+        },
+    }
+}
+
+// splits on "and" outside quotes
+fn conditions(expr: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut quote, mut start) = (None, 0);
+    for (i, c) in expr.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_q) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if expr[i..].starts_with(" and ") => {
+                parts.push(&expr[start..i]);
+                start = i + 5;
+            }
+            None => {}
+        }
+    }
+    parts.push(&expr[start..]);
+    parts
+}
+
+fn predicate(expr: &str) -> Result<Predicate, String> {
+    let expr = expr.trim();
+    if let Ok(position) = expr.parse::<usize>() {
+        return match position {
+            0 => Err("positions start at 1".to_string()),
+            position => Ok(Predicate::Position(position)),
+        };
+    }
+    if expr == "last()" {
+        return Ok(Predicate::Last);
+    }
+    for (function, make) in [
+        ("contains(", Predicate::Contains as fn(Operand, String) -> Predicate),
+        ("starts-with(", Predicate::StartsWith),
+    ] {
+        if let Some(args) = expr.strip_prefix(function).and_then(|args| args.strip_suffix(')')) {
+            let (left, right) = args.split_once(',').ok_or(format!("{} needs two arguments", expr))?;
+            return Ok(make(operand(left)?, literal(right)?));
+        }
+    }
+    if let Some((left, right)) = expr.split_once("!=") {
+        return Ok(Predicate::NotEquals(operand(left)?, literal(right)?));
+    }
+    if let Some((left, right)) = expr.split_once('=') {
+        return Ok(Predicate::Equals(operand(left)?, literal(right)?));
+    }
+    match operand(expr)? {
+        Operand::Text => Err(format!("unsupported predicate {}", expr)),
+        operand => Ok(Predicate::Exists(operand)),
+    }
+}
+
+fn step(descendant: bool, expr: &str) -> Result<Step, String> {
+    let (name, mut rest) = match expr.find('[') {
+        Some(i) => (&expr[..i], &expr[i..]),
+        None => (expr, ""),
+    };
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '*') {
+        return Err(format!("unsupported step {}", expr));
+    }
+    let mut predicates = Vec::new();
+    while !rest.is_empty() {
+        // the brackets are balanced, see split_steps
+        let (mut depth, mut quote, mut end) = (0, None, 0);
+        for (i, c) in rest.char_indices() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_q) => {}
+                None if c == '\'' || c == '"' => quote = Some(c),
+                None if c == '[' => depth += 1,
+                None if c == ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = i;
+                        break;
+                    }
+                }
+                None => {}
+            }
+        }
+        if !rest.starts_with('[') || end == 0 {
+            return Err(format!("unsupported step {}", expr));
+        }
+        predicates.push(conditions(&rest[1..end]).into_iter().map(predicate).collect::<Result<_, _>>()?);
+        rest = rest[end + 1..].trim_start();
+    }
+    Ok(Step {
+        descendant,
+        name: (name != "*").then(|| name.to_ascii_lowercase()),
+        predicates,
+    })
+}
+
+impl XPath {
+    pub fn parse(expr: &str) -> Result<XPath, String> {
+        let mut parts = split_steps(expr.trim())?;
+        let select = match parts.last().map(|(_slashes, part)| part.trim()) {
+            Some("text()") => Select::Text,
+            Some(part) if part.starts_with('@') => Select::Attr(part[1..].to_ascii_lowercase()),
+            _ => Select::Element,
+        };
+        if select != Select::Element {
+            parts.pop();
+        }
+        let steps = parts
+            .into_iter()
+            .map(|(slashes, part)| step(slashes == 2, part))
+            .collect::<Result<Vec<_>, _>>()?;
+        if steps.is_empty() {
+            return Err(format!("{} selects no element", expr));
+        }
+        Ok(XPath { steps, select })
+    }
+
+    // value of the first node the path selects, with the matched element as html
+    pub fn first(&self, document: &Html) -> Option<(String, String)> {
+        // document order, the first match is the first one in the page
+        let order: HashMap<NodeId, usize> =
+            document.tree.root().descendants().enumerate().map(|(i, node)| (node.id(), i)).collect();
+        let mut context: Option<Vec<ElementRef>> = None; // None is the document node
+        for step in &self.steps {
+            let mut next = Vec::new();
+            let mut seen = HashSet::new();
+            // a//b is a/descendant-or-self::node()/b, so positions count among the children of each parent
+            let parents: Vec<NodeRef<Node>> = match (&context, step.descendant) {
+                (None, false) => vec![document.tree.root()],
+                (None, true) => document
+                    .tree
+                    .root()
+                    .descendants()
+                    .filter(|node| node.value().is_document() || node.value().is_element())
+                    .collect(),
+                (Some(elements), false) => elements.iter().map(|element| **element).collect(),
+                (Some(elements), true) => elements
+                    .iter()
+                    .flat_map(|element| element.descendants().filter(|node| node.value().is_element()))
+                    .collect(),
+            };
+            for parent in parents {
+                let mut candidates: Vec<ElementRef> = parent
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|candidate| step.name.as_ref().is_none_or(|name| candidate.value().name() == name))
+                    .collect();
+                for conditions in &step.predicates {
+                    let count = candidates.len();
+                    candidates = candidates
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(i, candidate)| conditions.iter().all(|c| matches(c, candidate, i + 1, count)))
+                        .map(|(_i, candidate)| candidate)
+                        .collect();
+                }
+                next.extend(candidates.into_iter().filter(|candidate| seen.insert(candidate.id())));
+            }
+            next.sort_by_key(|element| order[&element.id()]);
+            context = Some(next);
+        }
+        let elements = context.unwrap_or_default();
+        let (element, value) = match &self.select {
+            Select::Element => elements.first().map(|element| (*element, element.text().collect::<String>()))?,
+            Select::Text => elements.first().map(|element| (*element, own_text(element)))?,
+            Select::Attr(name) => elements
+                .iter()
+                .find_map(|element| Some((*element, element.value().attr(name)?.to_string())))?,
+        };
+        Some((element.html(), value))
+    }
+}
+
+fn own_text(element: &ElementRef) -> String {
+    element.children().filter_map(|node| node.value().as_text().map(|text| text.to_string())).collect()
+}
+
+fn values(operand: &Operand, element: &ElementRef) -> Vec<String> {
+    match operand {
+        Operand::Attr(name) => element.value().attr(name).map(|value| value.to_string()).into_iter().collect(),
+        Operand::Text => vec![element.text().collect::<String>().trim().to_string()],
+        Operand::Child(name) => element
+            .children()
+            .filter_map(ElementRef::wrap)
+            .filter(|child| child.value().name() == name)
+            .map(|child| child.text().collect::<String>().trim().to_string())
+            .collect(),
+    }
+}
+
+fn matches(predicate: &Predicate, element: &ElementRef, position: usize, count: usize) -> bool {
+    match predicate {
+        Predicate::Position(p) => position == *p,
+        Predicate::Last => position == count,
+        Predicate::Exists(operand) => !values(operand, element).is_empty(),
+        Predicate::Equals(operand, s) => values(operand, element).iter().any(|v| v == s),
+        Predicate::NotEquals(operand, s) => values(operand, element).iter().any(|v| v != s),
+        Predicate::Contains(operand, s) => values(operand, element).iter().any(|v| v.contains(s.as_str())),
+        Predicate::StartsWith(operand, s) => values(operand, element).iter().any(|v| v.starts_with(s.as_str())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "<html><body><table>\
+        <tr><th>Lettera</th><td>101,50</td><td>Lettera</td></tr>\
+        <tr><th>Denaro</th><td>100,50</td><td>Denaro</td></tr>\
+        </table></body></html>";
+
+    fn first(expr: &str, html: &str) -> Option<String> {
+        XPath::parse(expr).unwrap().first(&Html::parse_document(html)).map(|(_html, value)| value)
+    }
+
+    #[test]
+    fn position_counts_per_parent() {
+        assert_eq!(first("//td[1]", TABLE).as_deref(), Some("101,50"));
+        assert_eq!(first("//td[2]", TABLE).as_deref(), Some("Lettera"));
+        assert_eq!(first("//td[3]", TABLE), None);
+        assert_eq!(first("//td[last()]", TABLE).as_deref(), Some("Lettera"));
+        assert_eq!(first("//tr[2]/td[1]", TABLE).as_deref(), Some("100,50"));
+        assert_eq!(first("//tr[last()]/td[last()]", TABLE).as_deref(), Some("Denaro"));
+        assert_eq!(first("//table//td[2]", TABLE).as_deref(), Some("Lettera"));
+        assert_eq!(first("//tr[th='Denaro']/td[1]", TABLE).as_deref(), Some("100,50"));
+    }
+
+    #[test]
+    fn position_after_filter() {
+        let html = r#"<ul><li class="a">1</li><li>2</li><li class="a">3</li></ul><ul><li class="a">4</li></ul>"#;
+        assert_eq!(first("//li[@class='a'][2]", html).as_deref(), Some("3"));
+        assert_eq!(first("//li[2][@class='a']", html), None);
+        assert_eq!(first("//ul[1]/li[last()]", html).as_deref(), Some("3"));
+        assert_eq!(first("/html/body/ul[2]/li[last()]", html).as_deref(), Some("4"));
+        assert_eq!(first("/html/body/ul[3]/li", html), None);
+    }
+
+    #[test]
+    fn first_in_document_order() {
+        let html = r#"<div><p><span class="x">inner</span></p><span class="x">outer</span></div>"#;
+        assert_eq!(first("//div//span[@class='x']", html).as_deref(), Some("inner"));
+        assert_eq!(first("//span[1]", html).as_deref(), Some("inner"));
+    }
+
+    #[test]
+    fn attribute_and_text() {
+        let html = r#"<div><span data-field="ask" data-value="2,5">x <b>y</b></span></div>"#;
+        assert_eq!(first("//span[@data-field='ask']/@data-value", html).as_deref(), Some("2,5"));
+        assert_eq!(first("//span[contains(@data-field,'as')]/text()", html).as_deref(), Some("x "));
+        assert_eq!(first("//div/*[1]", html).as_deref(), Some("x y"));
+    }
+}
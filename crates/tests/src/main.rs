@@ -1,3 +1,4 @@
+use common::naming::{parse_file_name, utc_offset};
 use glob::glob_with;
 use glob::MatchOptions;
 
@@ -13,21 +14,18 @@ fn main() {
     let source = "bnp";
     let mut max_entries = 2;
     let mut obsdatetimes = Vec::new();
-    for entry in glob_with(&format!("../estractor/data/output/{}-*.csv", source), options).unwrap() {
-        if let Ok(path) = entry {
-            let filename = String::from(path.to_str().unwrap());
-            // filename is in format <source>-<obsdatetime>.csv and <source> length is variable, so split at first '-' and get obsdatetime and remove .csv extension
-            let obsdatetime = (filename.split_at(filename.find('-').unwrap_or(0)+1).1).to_string();
-            let obsdatetime = obsdatetime.strip_suffix(".csv").unwrap_or(&obsdatetime).to_string();
-
-            obsdatetimes.push(obsdatetime.clone());
+    for path in glob_with(&format!("../estractor/data/output/{}-*.csv", source), options).unwrap().flatten() {
+        let filename = path.file_name().unwrap().to_str().unwrap();
+        // filename is in format <source>-<obsdatetime>.csv, see common::naming
+        if let Some(obs) = parse_file_name(filename, &utc_offset()) {
+            obsdatetimes.push(obs.obsdatetime.clone());
             max_entries -= 1;
             if max_entries == 0 {
                 break;
             }
         }
     }
-        // sort obsdatetimes in descending order
-        obsdatetimes.sort_by(|a, b| b.cmp(a));
-        println!("Observation datetimes for source {}: {:?}", source, obsdatetimes);
+    // sort obsdatetimes in descending order
+    obsdatetimes.sort_by(|a, b| b.cmp(a));
+    println!("Observation datetimes for source {}: {:?}", source, obsdatetimes);
 }
@@ -16,7 +16,8 @@ pub async fn read_csv(file_path: &str, isin: Option<&str>, has_header: bool) ->
             let mut lines = reader.lines();
             while let Some(line) = lines.next_line().await.unwrap() {
                 let line = line.trim();
-                if line.len() <= 0 {
+                if line.len() <= 0 || line.starts_with("-- ") {
+                    // skip blank lines and estractor markers such as "-- PARTIAL"
                     continue;
                 }
                 if is_header {
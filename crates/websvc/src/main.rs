@@ -1,5 +1,6 @@
 use std::{error::Error, fs::File};
 use std::io::{self, BufReader, prelude::*};
+use std::path::Path;
 use std::collections::HashMap;
 
 use std::sync::{Arc, Mutex};
@@ -7,6 +8,8 @@ use actix_web::middleware::Logger;
 use actix_web::{App, HttpResponse, HttpServer, Responder, get, web};
 //use serde::Serialize;
 use env_logger::Env;
+use common::markers::resolve_unchanged;
+use common::naming::{latest_observation, obs_file_name};
 
 mod ic_csv;
 use ic_csv::*;
@@ -66,34 +69,24 @@ fn check_dtime(source: &str, dt: &str) -> String {
 }
 
 fn get_latest_dtime(source: &str, arg: &str) -> String {
-    let mut latest_time = "1900-01-01-00-00-00".to_string();
-
-    for entry in std::fs::read_dir(arg).unwrap() {
-        // file format is <source>-<obsdatetime>.csv
-        let entry = entry.unwrap(); 
-        // get observation datetime from filename
-        let filename = entry.file_name().into_string().unwrap();
-        let obsdatetime = filename.split('-').nth(1).unwrap_or("").to_string();
-        // observation datetime is in format YYYY-MM-DD-HH-MM-SS
-        // check if source matches and if obsdatetime is greater than latest_time
-        if filename.starts_with(source) && obsdatetime > latest_time {
-            latest_time = obsdatetime;
-        }
+    // file format is <source>-<obsdatetime>.csv, see common::naming
+    match latest_observation(arg, source, "csv") {
+        Some(obs) => obs.obsdatetime,
+        None => "1900-01-01-00-00-00".to_string(),
     }
-    // return latest time  
-    latest_time
 }
 
 fn get_ds_name(source: Option<&str>, obsdatetime: Option<&str>) -> String {
-    let mut dt = "".to_string();
-    let output_path = if obsdatetime.is_some() {
-        dt = check_dtime(&source.unwrap_or("sources"), &obsdatetime.unwrap());
-        "/output"
-    } else {
-        ""
-    };
-    
-    format!("../estractor/data{}/{}-{}.csv", output_path, source.unwrap_or("sources"), dt)
+    let source = source.unwrap_or("sources");
+    match obsdatetime {
+        Some(obsdatetime) => {
+            let dt = check_dtime(source, obsdatetime);
+            let ds_path = format!("../estractor/data/output/{}", obs_file_name(source, &dt, "csv"));
+            // an unchanged observation points at the earlier file holding the quotes
+            resolve_unchanged(Path::new(&ds_path)).to_string_lossy().to_string()
+        }
+        None => format!("../estractor/data/{}-.csv", source),
+    }
 }
 
 // fn get_ds(key: &str, map: &SharedMap) -> File {